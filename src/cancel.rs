@@ -0,0 +1,150 @@
+use crate::*;
+use alloc::boxed::Box;
+use core::fmt;
+use core::future::Future;
+
+/// Checks that a future is cancel-safe by dropping it at every point
+/// where it could be suspended.
+///
+/// For each `k` from zero, a fresh future is built, polled `k` times
+/// (or until it completes) on a [`Wookie`] and then dropped. After
+/// each drop, every waker it was given must have been dropped too, and
+/// a check you supply must pass. The run ends once the future
+/// completes within `k` polls, or stalls: returns `Pending` without
+/// anything waking it, so that polling again would get no further.
+///
+/// ## Example
+///
+/// A future that takes a value out of a shared slot and only puts it
+/// back if it runs to completion loses the value when cancelled:
+///
+/// ```
+/// use core::cell::Cell;
+/// use std::rc::Rc;
+/// use wookie::{Cancel, CancelError, yield_now};
+///
+/// let slot = Rc::new(Cell::new(Some(42)));
+/// let failure = Cancel::new().run(
+///     || {
+///         let slot = slot.clone();
+///         async move {
+///             let value = slot.take();
+///             yield_now().await;
+///             slot.set(value);
+///         }
+///     },
+///     |_| if slot.get() == Some(42) { Ok(()) } else { Err("lost the value") },
+/// ).unwrap_err();
+/// // Dropping it before its first poll is fine, dropping it after is not.
+/// assert_eq!(failure.polls, 1);
+/// assert_eq!(failure.error, CancelError::Check("lost the value"));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Cancel {
+    polls: usize,
+}
+
+/// The result of a [`Cancel`] run in which every check passed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cancelled {
+    /// The number of times the future was built and dropped.
+    pub runs:     usize,
+    /// False if the future stalled, waiting for something outside it,
+    /// or the run stopped at the limit given to [`Cancel::polls`]
+    /// before the future completed.
+    pub complete: bool,
+}
+
+/// A drop that failed a [`Cancel`] run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CancelFailure<E> {
+    /// How many times the future was polled before it was dropped.
+    pub polls: usize,
+    /// What went wrong.
+    pub error: CancelError<E>,
+}
+
+/// What went wrong after dropping a future in a [`Cancel`] run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CancelError<E> {
+    /// Wakers given to the future were still alive after it was
+    /// dropped.
+    Leaked(Count),
+    /// The check returned an error.
+    Check(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for CancelFailure<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.error {
+            CancelError::Leaked(live) =>
+                write!(f, "dropping after {} polls leaked {} wakers", self.polls, live),
+            CancelError::Check(error) =>
+                write!(f, "dropping after {} polls failed with {:?}", self.polls, error),
+        }
+    }
+}
+
+impl Default for Cancel {
+    fn default() -> Self { Cancel::new() }
+}
+
+impl Cancel {
+    /// Creates a new [`Cancel`] with no limit.
+    #[inline(always)]
+    pub fn new() -> Self { Cancel { polls: usize::MAX } }
+
+    /// Gives up after dropping the future after `polls` polls.
+    #[inline(always)]
+    pub fn polls(self, polls: usize) -> Self { Cancel { polls } }
+
+    /// Runs the future to each suspension point in turn, stopping at
+    /// the first drop that leaks a waker or fails the check.
+    ///
+    /// `build` is called for a fresh future each time. `check` is
+    /// called after each drop with the number of polls before it.
+    ///
+    /// Futures that do not behave the same way each time they are
+    /// built may not be dropped at every suspension point.
+    ///
+    /// ## Example
+    ///
+    /// A future waiting for something that never happens stalls after
+    /// its first poll, so there is nothing more to try:
+    ///
+    /// ```
+    /// use wookie::{Cancel, Cancelled};
+    /// let result = Cancel::new().run(core::future::pending::<()>, |_| Ok::<(), ()>(()));
+    /// assert_eq!(result, Ok(Cancelled { runs: 2, complete: false }));
+    /// ```
+    pub fn run<F, E, Build, Check>(
+        &self,
+        mut build: Build,
+        mut check: Check,
+    ) -> Result<Cancelled, CancelFailure<E>>
+    where F: Future,
+          Build: FnMut() -> F,
+          Check: FnMut(usize) -> Result<(), E> {
+        let mut polls = 0;
+        loop {
+            let mut wookie = Box::pin(Wookie::new(build()));
+            let (mut complete, mut stalled) = (false, false);
+            for _ in 0..polls {
+                let woken = wookie.as_mut().woken();
+                if wookie.as_mut().poll().is_ready() { complete = true; break; }
+                stalled = wookie.as_mut().woken() == woken;
+            }
+            let live = Wookie::drop_with_stats(wookie).live();
+            if live != 0 {
+                return Err(CancelFailure { polls, error: CancelError::Leaked(live) });
+            }
+            if let Err(error) = check(polls) {
+                return Err(CancelFailure { polls, error: CancelError::Check(error) });
+            }
+            if complete || stalled || polls == self.polls {
+                return Ok(Cancelled { runs: polls + 1, complete });
+            }
+            polls += 1;
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{Count, Stats};
+use core::fmt;
+
+/// What a stepper should do when one of its optional checks fails.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Check {
+    /// Don't check.
+    Off,
+    /// Remember the failure so it can be inspected later.
+    Record,
+    /// Panic with a description of the failure.
+    Panic,
+}
+
+/// A poll that returned `Pending` without keeping a waker or waking
+/// one, so nothing will ever wake the future again.
+///
+/// Reported by [`crate::Wookie::check_lost_wakeups`] and
+/// [`crate::Local::check_lost_wakeups`].
+pub struct LostWakeup {
+    /// Which poll it was, counting from 1.
+    pub poll:  Count,
+    /// The waker statistics just after the poll.
+    pub stats: Stats,
+}
+
+impl fmt::Display for LostWakeup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "Lost wakeup: poll {} returned Pending without keeping or waking a waker ({})",
+            self.poll, self.stats
+        )
+    }
+}
+
+impl LostWakeup {
+    /// Checks the outcome of a poll, returning a report if it lost its
+    /// wakeup. `woken` is the woken count from before the poll.
+    pub(crate) fn check(poll: Count, pending: bool, woken: Count, stats: Stats) -> Option<LostWakeup> {
+        if pending && stats.live() == 0 && stats.woken == woken {
+            Some(LostWakeup { poll, stats })
+        } else {
+            None
+        }
+    }
+
+    /// Acts on the outcome of [`LostWakeup::check`] according to `mode`.
+    #[track_caller]
+    pub(crate) fn handle(mode: Check, lost: Option<LostWakeup>, slot: &mut Option<LostWakeup>) {
+        if let Some(lost) = lost {
+            match mode {
+                Check::Off => {}
+                Check::Record => *slot = Some(lost),
+                Check::Panic => panic!("{}", lost),
+            }
+        }
+    }
+}
+
+/// The payload of a panic caught by `try_poll`, as returned by
+/// [`std::panic::catch_unwind`].
+#[cfg(feature="std")]
+pub type PanicPayload = std::boxed::Box<dyn core::any::Any + Send + 'static>;
+
+/// Polls `future`, catching a panic. For `try_poll` to hand to `step`,
+/// so that only the future's own panics are caught.
+#[cfg(feature="std")]
+pub(crate) fn catch_poll<F: core::future::Future>(
+    future: core::pin::Pin<&mut F>,
+    ctx: &mut core::task::Context,
+) -> core::task::Poll<Result<F::Output, PanicPayload>> {
+    use core::task::Poll;
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| future.poll(ctx))) {
+        Ok(Poll::Ready(out)) => Poll::Ready(Ok(out)),
+        Ok(Poll::Pending) => Poll::Pending,
+        Err(payload) => Poll::Ready(Err(payload)),
+    }
+}
+
+/// Aborts the process with a message. Without `std`, we do this by
+/// panicking while panicking.
+#[cold]
+pub(crate) fn abort(msg: fmt::Arguments) -> ! {
+    #[cfg(feature="std")]
+    {
+        std::eprintln!("{}", msg);
+        std::process::abort()
+    }
+    #[cfg(not(feature="std"))]
+    {
+        struct Bomb;
+        impl Drop for Bomb {
+            fn drop(&mut self) { panic!("wookie: aborting") }
+        }
+        let _bomb = Bomb;
+        panic!("{}", msg)
+    }
+}
@@ -1,8 +1,11 @@
+use crate::{Count, Stats};
 use core::future::Future;
 use core::mem::ManuallyDrop;
 use core::pin::Pin;
 use core::task::{Context, Poll};
 use dummy_waker::dummy_waker;
+#[cfg(feature="futures-core")]
+use futures_core::Stream;
 
 /// A single-stepping executor whose waker does absolutely nothing,
 /// but quite quickly.
@@ -15,14 +18,108 @@ use dummy_waker::dummy_waker;
 /// dummy!(future: async { true });
 /// assert_eq!(future.poll(), Poll::Ready(true));
 /// ```
-#[repr(transparent)]
-pub struct Dummy<F>(F);
+pub struct Dummy<F> {
+    future:     F,
+    polls:      Count,
+    pending:    Count,
+    terminated: bool,
+    repoll:     bool,
+}
 
-impl<F: Future> Dummy<F> {
+impl<F> Dummy<F> {
     #[doc(hidden)]
     #[inline(always)]
-    pub fn new(future: F) -> Self{ Dummy(future) }
+    pub fn new(future: F) -> Self{ Dummy { future, polls: 0, pending: 0, terminated: false, repoll: false } }
+
+    /// Returns how many times the future has been polled. This count
+    /// is cumulative, it is never reset and is allowed to overflow.
+    #[inline(always)]
+    pub fn polls(self: &mut Pin<&mut Self>) -> Count { self.as_mut().project().polls }
+
+    /// Returns how many polls of the future returned `Pending`.
+    #[inline(always)]
+    pub fn pending(self: &mut Pin<&mut Self>) -> Count { self.as_mut().project().pending }
+
+    /// Returns statistics about polling. As our waker does nothing, it
+    /// can't count anything either, so only `polls` and `pending` are
+    /// filled in.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use wookie::{assert_stats, dummy};
+    /// dummy!(future: async { true });
+    /// future.poll();
+    /// assert_stats!(future.stats(), polls: 1, pending: 0);
+    /// ```
+    #[inline(always)]
+    pub fn stats(self: &mut Pin<&mut Self>) -> Stats {
+        let this = self.as_mut().project();
+        Stats { polls: this.polls, pending: this.pending, ..Stats::default() }
+    }
 
+    /// Returns whether the future has completed or the stream has
+    /// ended.
+    #[inline(always)]
+    pub fn is_terminated(self: &mut Pin<&mut Self>) -> bool {
+        self.as_mut().project().terminated
+    }
+
+    /// Allows polling again after the future has completed or the
+    /// stream has ended, for testing fused futures and streams. By
+    /// default, this panics, as many futures do not expect it.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::dummy;
+    /// dummy!(future: poll_fn(|_| Poll::Ready(())));
+    /// assert_eq!(future.poll(), Poll::Ready(()));
+    /// assert!(future.is_terminated());
+    /// future.allow_repoll(true);
+    /// assert_eq!(future.poll(), Poll::Ready(()));
+    /// ```
+    #[inline(always)]
+    pub fn allow_repoll(self: &mut Pin<&mut Self>, allowed: bool) {
+        self.as_mut().project().repoll = allowed;
+    }
+
+    /// Remembers that the future has completed or the stream has
+    /// ended if `done`.
+    #[inline(always)]
+    fn terminate_if(self: &mut Pin<&mut Self>, done: bool) {
+        if done { self.as_mut().project().terminated = true; }
+    }
+
+    /// Runs one poll of the future with our waker.
+    #[inline(always)]
+    fn step<R>(
+        self: &mut Pin<&mut Self>,
+        f: impl FnOnce(Pin<&mut F>, &mut Context) -> Poll<R>,
+    ) -> Poll<R> {
+        let this = self.as_mut().project();
+        assert!(
+            !this.terminated || this.repoll,
+            "Dummy polled again after it completed. Use allow_repoll to allow this."
+        );
+        let waker = ManuallyDrop::new(dummy_waker());
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let mut ctx = Context::from_waker(&waker);
+        let ret = f(future, &mut ctx);
+        this.polls = this.polls.wrapping_add(1);
+        if ret.is_pending() { this.pending = this.pending.wrapping_add(1) }
+        ret
+    }
+
+    #[inline(always)]
+    fn project(self: Pin<&mut Self>) -> &mut Self {
+        unsafe { Pin::into_inner_unchecked(self) }
+    }
+}
+
+impl<F: Future> Dummy<F> {
     /// Polls the contained future once.
     ///
     /// ## Example
@@ -37,16 +134,20 @@ impl<F: Future> Dummy<F> {
     pub fn poll(
         self: &mut Pin<&mut Self>
     ) -> Poll<<F as Future>::Output> {
-        let this = self.as_mut().project();
-        let waker = ManuallyDrop::new(dummy_waker());
-        let future = unsafe { Pin::new_unchecked(&mut this.0) };
-        let mut ctx = Context::from_waker(&waker);
-        Future::poll(future, &mut ctx)
+        let ret = self.step(Future::poll);
+        self.terminate_if(ret.is_ready());
+        ret
     }
+}
 
+#[cfg(feature="futures-core")]
+impl<F: Stream> Dummy<F> {
+    /// Polls the contained stream for its next item once.
     #[inline(always)]
-    fn project(self: Pin<&mut Self>) -> &mut Self {
-        unsafe { Pin::into_inner_unchecked(self) }
+    pub fn poll_next(self: &mut Pin<&mut Self>) -> Poll<Option<F::Item>> {
+        let ret = self.step(Stream::poll_next);
+        self.terminate_if(matches!(ret, Poll::Ready(None)));
+        ret
     }
 }
 
@@ -0,0 +1,193 @@
+use crate::mock::register;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+#[cfg(any(feature="futures-io", feature="tokio"))]
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+/// Creates a bounded in-memory duplex pipe, returning its two ends.
+///
+/// Each direction buffers up to `capacity` bytes. Reading from an
+/// empty buffer or writing to a full one returns `Pending` and the
+/// other end wakes the waker when data moves. Dropping or closing an
+/// end makes reads on the other end return EOF once the buffer is
+/// empty, and writes on the other end fail with `BrokenPipe`.
+///
+/// ## Panics
+///
+/// If `capacity` is zero.
+///
+/// ## Example
+///
+/// Stepping a client and server against each other:
+///
+/// ```
+/// use core::future::poll_fn;
+/// use wookie::{Multi, duplex};
+/// let (mut client, mut server) = duplex(4);
+/// let mut tasks = Multi::new();
+/// let s = tasks.spawn(async move {
+///     let mut buf = [0u8; 5];
+///     let mut len = 0;
+///     while len < 5 {
+///         len += poll_fn(|ctx| server.poll_read(ctx, &mut buf[len..])).await.unwrap();
+///     }
+///     buf
+/// });
+/// let c = tasks.spawn(async move {
+///     let mut len = 0;
+///     while len < 5 {
+///         len += poll_fn(|ctx| client.poll_write(ctx, &b"hello"[len..])).await.unwrap();
+///     }
+///     *b"bye!!"
+/// });
+/// assert_eq!(tasks.poll_while_woken(), vec![(c, *b"bye!!"), (s, *b"hello")]);
+/// // the server waited for data twice, the client for space once.
+/// assert_eq!(tasks.stats(s).woken, 2);
+/// assert_eq!(tasks.stats(c).woken, 1);
+/// ```
+pub fn duplex(capacity: usize) -> (Duplex, Duplex) {
+    assert!(capacity > 0, "duplex capacity must be at least 1");
+    let a = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let b = Arc::new(Mutex::new(Pipe::new(capacity)));
+    (Duplex { read: a.clone(), write: b.clone() }, Duplex { read: b, write: a })
+}
+
+/// One end of an in-memory duplex pipe. Created by [`duplex`].
+///
+/// Implements the `futures-io` and `tokio` I/O traits when the
+/// features of the same name are enabled, but can also be used
+/// directly through [`Duplex::poll_read`] and [`Duplex::poll_write`].
+pub struct Duplex {
+    read:  Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+struct Pipe {
+    buf:         VecDeque<u8>,
+    capacity:    usize,
+    closed:      bool,
+    read_waker:  Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Self {
+        Pipe { buf: VecDeque::new(), capacity, closed: false, read_waker: None, write_waker: None }
+    }
+}
+
+impl Duplex {
+    /// Attempts to read into `buf` from the other end.
+    pub fn poll_read(&mut self, ctx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut pipe = lock(&self.read);
+        if pipe.buf.is_empty() && !buf.is_empty() {
+            if pipe.closed { return Poll::Ready(Ok(0)); }
+            register(&mut pipe.read_waker, ctx.waker());
+            return Poll::Pending;
+        }
+        let len = pipe.buf.len().min(buf.len());
+        for (byte, slot) in pipe.buf.drain(..len).zip(buf.iter_mut()) { *slot = byte; }
+        let waker = pipe.write_waker.take();
+        drop(pipe);
+        if let Some(waker) = waker { waker.wake() }
+        Poll::Ready(Ok(len))
+    }
+
+    /// Attempts to write `data` to the other end.
+    pub fn poll_write(&mut self, ctx: &mut Context, data: &[u8]) -> Poll<io::Result<usize>> {
+        let mut pipe = lock(&self.write);
+        if pipe.closed { return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())); }
+        let space = pipe.capacity - pipe.buf.len();
+        if space == 0 && !data.is_empty() {
+            register(&mut pipe.write_waker, ctx.waker());
+            return Poll::Pending;
+        }
+        let len = space.min(data.len());
+        pipe.buf.extend(&data[..len]);
+        let waker = pipe.read_waker.take();
+        drop(pipe);
+        if let Some(waker) = waker { waker.wake() }
+        Poll::Ready(Ok(len))
+    }
+
+    /// Closes this end for writing. Reads on the other end return EOF
+    /// once they have read everything already written.
+    pub fn close(&mut self) {
+        let waker = {
+            let mut pipe = lock(&self.write);
+            pipe.closed = true;
+            pipe.read_waker.take()
+        };
+        if let Some(waker) = waker { waker.wake() }
+    }
+}
+
+impl Drop for Duplex {
+    fn drop(&mut self) {
+        self.close();
+        let waker = {
+            let mut pipe = lock(&self.read);
+            pipe.closed = true;
+            pipe.write_waker.take()
+        };
+        if let Some(waker) = waker { waker.wake() }
+    }
+}
+
+fn lock(pipe: &Mutex<Pipe>) -> MutexGuard<'_, Pipe> {
+    pipe.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature="futures-io")]
+impl futures_io::AsyncRead for Duplex {
+    fn poll_read(self: Pin<&mut Self>, ctx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Duplex::poll_read(self.get_mut(), ctx, buf)
+    }
+}
+
+#[cfg(feature="futures-io")]
+impl futures_io::AsyncWrite for Duplex {
+    fn poll_write(self: Pin<&mut Self>, ctx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Duplex::poll_write(self.get_mut(), ctx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        self.get_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature="tokio")]
+impl tokio::io::AsyncRead for Duplex {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        Duplex::poll_read(self.get_mut(), ctx, buf.initialize_unfilled())
+            .map_ok(|len| buf.advance(len))
+    }
+}
+
+#[cfg(feature="tokio")]
+impl tokio::io::AsyncWrite for Duplex {
+    fn poll_write(self: Pin<&mut Self>, ctx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Duplex::poll_write(self.get_mut(), ctx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        self.get_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
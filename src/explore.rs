@@ -0,0 +1,272 @@
+use crate::*;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Runs a set of tasks on a [`Multi`] once for every distinct order
+/// in which woken tasks could be polled, looking for one that fails a
+/// check. This is bounded model checking for poll orders.
+///
+/// The search always tries to keep polling the task that was polled
+/// last before switching to another one. Switching away from a task
+/// that is still woken is a *preemption*. Bounding the number of
+/// preemptions keeps the search small while still finding most bugs.
+///
+/// ## Example
+///
+/// A racy increment that only fails if the tasks interleave:
+///
+/// ```
+/// use core::cell::Cell;
+/// use std::rc::Rc;
+/// use wookie::{Explore, Multi, yield_now};
+///
+/// let mut setup = |tasks: &mut Multi<()>| {
+///     let counter = Rc::new(Cell::new(0));
+///     for _ in 0..2 {
+///         let counter = counter.clone();
+///         tasks.spawn(async move {
+///             let n = counter.get();
+///             yield_now().await;
+///             counter.set(n + 1);
+///         });
+///     }
+///     counter
+/// };
+/// let mut check = |_: &mut Multi<()>, counter: Rc<Cell<i32>>, _| {
+///     if counter.get() == 2 { Ok(()) } else { Err(counter.get()) }
+/// };
+/// let failure = Explore::new().preemptions(1).run(&mut setup, &mut check).unwrap_err();
+/// assert_eq!(failure.error, 1);
+///
+/// // The trace replays the exact interleaving that failed.
+/// assert_eq!(Explore::replay(failure.trace, &mut setup, &mut check), Err(1));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Explore {
+    depth:       usize,
+    preemptions: usize,
+    schedules:   usize,
+}
+
+/// The result of an [`Explore`] run in which every check passed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Explored {
+    /// The number of schedules that were run.
+    pub schedules: usize,
+    /// False if the search stopped at the limit given to
+    /// [`Explore::schedules`] before trying every schedule.
+    pub complete:  bool,
+}
+
+/// A schedule that failed the check of an [`Explore`] run.
+#[derive(Clone, Debug)]
+pub struct ExploreFailure<E> {
+    /// The order in which tasks were polled. Pass it to
+    /// [`Explore::replay`] or [`Multi::with_trace`] to replay it.
+    pub trace: Trace,
+    /// The error returned by the check.
+    pub error: E,
+    /// The number of schedules that ran before this one.
+    pub schedule: usize,
+}
+
+impl<E: fmt::Debug> fmt::Display for ExploreFailure<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "schedule {} failed with {:?}. Replay with trace [{}]", self.schedule, self.error, self.trace)
+    }
+}
+
+/// The order in which a [`Multi`] polled its tasks.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Trace(Vec<Task>);
+
+impl Trace {
+    /// Creates a trace from the indices of tasks in spawn order, as
+    /// printed when a [`ExploreFailure`] is displayed.
+    pub fn from_indices<I: IntoIterator<Item = usize>>(indices: I) -> Self {
+        Trace(indices.into_iter().map(Task).collect())
+    }
+
+    /// The tasks, in the order they were polled.
+    #[inline(always)]
+    pub fn tasks(&self) -> &[Task] { &self.0 }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, task) in self.0.iter().enumerate() {
+            if i > 0 { f.write_str(", ")?; }
+            write!(f, "{}", task.index())?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Explore {
+    fn default() -> Self { Explore::new() }
+}
+
+impl Explore {
+    /// Creates a new [`Explore`] with no bounds.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Explore { depth: usize::MAX, preemptions: usize::MAX, schedules: usize::MAX }
+    }
+
+    /// Only branches on the first `depth` scheduling decisions of each
+    /// run. Later decisions never preempt.
+    #[inline(always)]
+    pub fn depth(self, depth: usize) -> Self { Explore { depth, ..self } }
+
+    /// Only tries schedules with at most `preemptions` preemptions.
+    #[inline(always)]
+    pub fn preemptions(self, preemptions: usize) -> Self { Explore { preemptions, ..self } }
+
+    /// Gives up after running `schedules` schedules.
+    #[inline(always)]
+    pub fn schedules(self, schedules: usize) -> Self { Explore { schedules, ..self } }
+
+    /// Runs every schedule within the bounds, stopping at the first
+    /// one that fails the check.
+    ///
+    /// For each schedule, `setup` is called with a fresh [`Multi`] to
+    /// spawn tasks on, then woken tasks are polled until none are
+    /// woken. Finally, `check` is called with the [`Multi`], whatever
+    /// `setup` returned and the outputs of the tasks that completed.
+    ///
+    /// ## Panics
+    ///
+    /// If the tasks do not wake the same way when a schedule is
+    /// replayed, i.e. they are not deterministic.
+    ///
+    /// If `setup`, `check` or a task panics, the panic is not caught.
+    /// With the `std` feature, the trace of the schedule so far is
+    /// printed to stderr, so it can be replayed with
+    /// [`Trace::from_indices`] and [`Explore::replay`].
+    pub fn run<'a, T, S, E, Setup, Check>(
+        &self,
+        mut setup: Setup,
+        mut check: Check,
+    ) -> Result<Explored, ExploreFailure<E>>
+    where Setup: FnMut(&mut Multi<'a, T>) -> S,
+          Check: FnMut(&mut Multi<'a, T>, S, Vec<(Task, T)>) -> Result<(), E> {
+        let mut prefix = Vec::new();
+        let mut schedules = 0;
+        while schedules < self.schedules {
+            let script = Script::explore(*self, prefix);
+            let mut tasks = Multi::with_script(script);
+            let state = setup(&mut tasks);
+            let done = tasks.poll_while_woken();
+            let result = check(&mut tasks, state, done);
+            let script = tasks.into_script();
+            if let Err(error) = result {
+                return Err(ExploreFailure { trace: Trace(script.trace), error, schedule: schedules });
+            }
+            schedules += 1;
+            match script.next_prefix() {
+                Some(next) => prefix = next,
+                None => return Ok(Explored { schedules, complete: true }),
+            }
+        }
+        Ok(Explored { schedules, complete: false })
+    }
+
+    /// Runs a single schedule from a [`Trace`] in the same way as
+    /// [`Explore::run`] does.
+    pub fn replay<'a, T, S, E, Setup, Check>(
+        trace: Trace,
+        mut setup: Setup,
+        mut check: Check,
+    ) -> Result<(), E>
+    where Setup: FnMut(&mut Multi<'a, T>) -> S,
+          Check: FnMut(&mut Multi<'a, T>, S, Vec<(Task, T)>) -> Result<(), E> {
+        let mut tasks = Multi::with_trace(trace);
+        let state = setup(&mut tasks);
+        let done = tasks.poll_while_woken();
+        check(&mut tasks, state, done)
+    }
+}
+
+/// Scheduling decisions for a [`Multi`] that come from a trace being
+/// replayed or an [`Explore`] run.
+pub(crate) struct Script {
+    bounds:      Option<Explore>,
+    prefix:      Vec<usize>,
+    decisions:   Vec<Decision>,
+    preemptions: usize,
+    trace:       Vec<Task>,
+}
+
+struct Decision {
+    chosen:  usize,
+    options: usize,
+}
+
+impl Script {
+    pub(crate) fn replay(trace: Trace) -> Self {
+        let prefix = trace.0.into_iter().map(Task::index).collect();
+        Script { bounds: None, prefix, decisions: Vec::new(), preemptions: 0, trace: Vec::new() }
+    }
+
+    fn explore(bounds: Explore, prefix: Vec<usize>) -> Self {
+        Script { bounds: Some(bounds), prefix, decisions: Vec::new(), preemptions: 0, trace: Vec::new() }
+    }
+
+    /// Prints the schedule so far if we are exploring, so that one
+    /// that panicked can be replayed.
+    #[cfg(feature="std")]
+    pub(crate) fn report_panic(&self) {
+        if self.bounds.is_none() { return; }
+        let trace = Trace(self.trace.clone());
+        std::eprintln!("wookie: Explore schedule panicked. Replay with trace [{}]", trace);
+    }
+
+    /// Picks a task from `woken`, which is in round-robin order
+    /// starting after `last`.
+    pub(crate) fn choose(&mut self, last: Option<usize>, woken: &[usize]) -> usize {
+        let task = match self.bounds {
+            None => self.choose_replay(woken),
+            Some(bounds) => self.choose_explore(bounds, last, woken),
+        };
+        self.trace.push(Task(task));
+        task
+    }
+
+    fn choose_replay(&mut self, woken: &[usize]) -> usize {
+        let step = self.trace.len();
+        match self.prefix.get(step) {
+            None => woken[0],
+            Some(task) => {
+                assert!(woken.contains(task), "Trace step {} polls task {}, which is not woken!", step, task);
+                *task
+            }
+        }
+    }
+
+    fn choose_explore(&mut self, bounds: Explore, last: Option<usize>, woken: &[usize]) -> usize {
+        // If the last task is still woken, it is the last in round
+        // robin order. Continuing with it is the default choice.
+        let mut candidates: Vec<usize> = woken.to_vec();
+        let preemptible = last.is_some() && last == woken.last().copied();
+        if preemptible { candidates.rotate_right(1); }
+        let step = self.decisions.len();
+        let options = if step >= bounds.depth || (preemptible && self.preemptions >= bounds.preemptions) {
+            1
+        } else {
+            candidates.len()
+        };
+        let chosen = self.prefix.get(step).copied().unwrap_or(0);
+        assert!(chosen < options, "Tasks woke differently when replaying decision {}. Are they deterministic?", step);
+        if preemptible && chosen > 0 { self.preemptions += 1; }
+        self.decisions.push(Decision { chosen, options });
+        candidates[chosen]
+    }
+
+    /// The choices that lead to the next schedule to explore, if any.
+    fn next_prefix(&self) -> Option<Vec<usize>> {
+        let last = self.decisions.iter().rposition(|d| d.chosen + 1 < d.options)?;
+        let mut prefix: Vec<usize> = self.decisions[..last].iter().map(|d| d.chosen).collect();
+        prefix.push(self.decisions[last].chosen + 1);
+        Some(prefix)
+    }
+}
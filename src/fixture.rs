@@ -0,0 +1,69 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Returns a future that wakes itself and returns `Pending` once, then
+/// completes. The simplest suspension point there is.
+///
+/// ## Example
+///
+/// ```
+/// use core::task::Poll;
+/// use wookie::{wookie, yield_now};
+/// wookie!(future: yield_now());
+/// assert_eq!(future.poll(), Poll::Pending);
+/// assert_eq!(future.woken(), 1);
+/// assert_eq!(future.poll(), Poll::Ready(()));
+/// ```
+#[inline(always)]
+pub fn yield_now() -> YieldNow { YieldNow { yielded: false } }
+
+/// A future that yields once. Created by [`yield_now`].
+#[derive(Debug)]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()> {
+        if self.yielded { return Poll::Ready(()); }
+        self.yielded = true;
+        ctx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Returns a stream of the items of an iterator, each of which is
+/// ready straight away.
+///
+/// ## Example
+///
+/// ```
+/// use core::task::Poll;
+/// use wookie::{iter_stream, wookie};
+/// wookie!(stream: iter_stream(1..3));
+/// assert_eq!(stream.poll_next(), Poll::Ready(Some(1)));
+/// assert_eq!(stream.poll_next(), Poll::Ready(Some(2)));
+/// assert_eq!(stream.poll_next(), Poll::Ready(None));
+/// ```
+#[cfg(feature="futures-core")]
+#[inline(always)]
+pub fn iter_stream<I: IntoIterator>(iter: I) -> IterStream<I::IntoIter> {
+    IterStream { iter: iter.into_iter() }
+}
+
+/// A stream of the items of an iterator. Created by [`iter_stream`].
+#[cfg(feature="futures-core")]
+#[derive(Debug)]
+pub struct IterStream<I> {
+    iter: I,
+}
+
+#[cfg(feature="futures-core")]
+impl<I: Iterator + Unpin> futures_core::Stream for IterStream<I> {
+    type Item = I::Item;
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<Option<I::Item>> {
+        Poll::Ready(self.iter.next())
+    }
+}
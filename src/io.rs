@@ -0,0 +1,166 @@
+use crate::Wookie;
+use core::pin::Pin;
+use core::task::Poll;
+use std::io;
+
+/// Steps a [`Wookie`] holding a `futures-io` reader or writer, one
+/// call at a time, under its counting waker. Created by
+/// [`Wookie::futures_io`].
+///
+/// Each method polls once, exactly as [`Wookie::poll`] does, so the
+/// stats tell you which call registered (or lost) a waker.
+///
+/// ## Example
+///
+/// ```
+/// use core::task::Poll;
+/// use wookie::{duplex, wookie};
+/// let (client, server) = duplex(4);
+/// wookie!(rx: server);
+/// wookie!(tx: client);
+/// let mut buf = [0u8; 4];
+/// assert_eq!(rx.futures_io().poll_read(&mut buf).map(Result::ok), Poll::Pending);
+/// rx.stats().assert(1, 0, 0); // the read kept a waker
+/// assert_eq!(tx.futures_io().poll_write(b"hi").map(Result::ok), Poll::Ready(Some(2)));
+/// rx.stats().assert(1, 1, 1); // which the write woke
+/// assert_eq!(rx.futures_io().poll_read(&mut buf).map(Result::ok), Poll::Ready(Some(2)));
+/// assert_eq!(&buf[..2], b"hi");
+/// ```
+#[cfg(feature="futures-io")]
+pub struct FuturesIo<'a, F> {
+    wookie: Pin<&'a mut Wookie<F>>,
+}
+
+#[cfg(feature="futures-io")]
+impl<'a, F> FuturesIo<'a, F> {
+    pub(crate) fn new(wookie: Pin<&'a mut Wookie<F>>) -> Self { FuturesIo { wookie } }
+
+    /// Polls the contained reader's `poll_read` once.
+    #[inline(always)]
+    pub fn poll_read(&mut self, buf: &mut [u8]) -> Poll<io::Result<usize>>
+    where F: futures_io::AsyncRead {
+        self.wookie.step(|io, ctx| io.poll_read(ctx, buf))
+    }
+
+    /// Polls the contained reader's `poll_fill_buf` once.
+    #[inline(always)]
+    pub fn poll_fill_buf(&mut self) -> Poll<io::Result<&[u8]>>
+    where F: futures_io::AsyncBufRead {
+        let poll = self.wookie.step(|io, ctx| {
+            io.poll_fill_buf(ctx).map_ok(|buf| (buf.as_ptr(), buf.len()))
+        });
+        // Safety: the buffer belongs to the reader, which we borrow
+        // for as long as the slice.
+        poll.map_ok(|(ptr, len)| unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
+
+    /// Calls the contained reader's `consume`.
+    #[inline(always)]
+    pub fn consume(&mut self, amt: usize)
+    where F: futures_io::AsyncBufRead {
+        self.wookie.pinned().consume(amt)
+    }
+
+    /// Polls the contained writer's `poll_write` once.
+    #[inline(always)]
+    pub fn poll_write(&mut self, bytes: &[u8]) -> Poll<io::Result<usize>>
+    where F: futures_io::AsyncWrite {
+        self.wookie.step(|io, ctx| io.poll_write(ctx, bytes))
+    }
+
+    /// Polls the contained writer's `poll_flush` once.
+    #[inline(always)]
+    pub fn poll_flush(&mut self) -> Poll<io::Result<()>>
+    where F: futures_io::AsyncWrite {
+        self.wookie.step(futures_io::AsyncWrite::poll_flush)
+    }
+
+    /// Polls the contained writer's `poll_close` once.
+    #[inline(always)]
+    pub fn poll_close(&mut self) -> Poll<io::Result<()>>
+    where F: futures_io::AsyncWrite {
+        self.wookie.step(futures_io::AsyncWrite::poll_close)
+    }
+}
+
+/// Steps a [`Wookie`] holding a `tokio` reader or writer, one call at
+/// a time, under its counting waker. Created by [`Wookie::tokio_io`].
+///
+/// Each method polls once, exactly as [`Wookie::poll`] does, so the
+/// stats tell you which call registered (or lost) a waker.
+///
+/// ## Example
+///
+/// ```
+/// use core::task::Poll;
+/// use wookie::{duplex, wookie};
+/// let (client, server) = duplex(4);
+/// wookie!(rx: server);
+/// wookie!(tx: client);
+/// let mut buf = [0u8; 4];
+/// assert_eq!(rx.tokio_io().poll_read(&mut buf).map(Result::ok), Poll::Pending);
+/// assert_eq!(tx.tokio_io().poll_write(b"hi").map(Result::ok), Poll::Ready(Some(2)));
+/// assert_eq!(tx.tokio_io().poll_shutdown().map(Result::ok), Poll::Ready(Some(())));
+/// rx.stats().assert(1, 1, 1);
+/// assert_eq!(rx.tokio_io().poll_read(&mut buf).map(Result::ok), Poll::Ready(Some(2)));
+/// assert_eq!(&buf[..2], b"hi");
+/// ```
+#[cfg(feature="tokio")]
+pub struct TokioIo<'a, F> {
+    wookie: Pin<&'a mut Wookie<F>>,
+}
+
+#[cfg(feature="tokio")]
+impl<'a, F> TokioIo<'a, F> {
+    pub(crate) fn new(wookie: Pin<&'a mut Wookie<F>>) -> Self { TokioIo { wookie } }
+
+    /// Polls the contained reader's `poll_read` once, returning how
+    /// many bytes it read into `buf`.
+    #[inline(always)]
+    pub fn poll_read(&mut self, buf: &mut [u8]) -> Poll<io::Result<usize>>
+    where F: tokio::io::AsyncRead {
+        let mut buf = tokio::io::ReadBuf::new(buf);
+        self.wookie.step(|io, ctx| io.poll_read(ctx, &mut buf))
+            .map_ok(|()| buf.filled().len())
+    }
+
+    /// Polls the contained reader's `poll_fill_buf` once.
+    #[inline(always)]
+    pub fn poll_fill_buf(&mut self) -> Poll<io::Result<&[u8]>>
+    where F: tokio::io::AsyncBufRead {
+        let poll = self.wookie.step(|io, ctx| {
+            io.poll_fill_buf(ctx).map_ok(|buf| (buf.as_ptr(), buf.len()))
+        });
+        // Safety: the buffer belongs to the reader, which we borrow
+        // for as long as the slice.
+        poll.map_ok(|(ptr, len)| unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
+
+    /// Calls the contained reader's `consume`.
+    #[inline(always)]
+    pub fn consume(&mut self, amt: usize)
+    where F: tokio::io::AsyncBufRead {
+        self.wookie.pinned().consume(amt)
+    }
+
+    /// Polls the contained writer's `poll_write` once.
+    #[inline(always)]
+    pub fn poll_write(&mut self, bytes: &[u8]) -> Poll<io::Result<usize>>
+    where F: tokio::io::AsyncWrite {
+        self.wookie.step(|io, ctx| io.poll_write(ctx, bytes))
+    }
+
+    /// Polls the contained writer's `poll_flush` once.
+    #[inline(always)]
+    pub fn poll_flush(&mut self) -> Poll<io::Result<()>>
+    where F: tokio::io::AsyncWrite {
+        self.wookie.step(tokio::io::AsyncWrite::poll_flush)
+    }
+
+    /// Polls the contained writer's `poll_shutdown` once.
+    #[inline(always)]
+    pub fn poll_shutdown(&mut self) -> Poll<io::Result<()>>
+    where F: tokio::io::AsyncWrite {
+        self.wookie.step(tokio::io::AsyncWrite::poll_shutdown)
+    }
+}
@@ -46,6 +46,50 @@
 //! future.stats().assert(0, 0, 0);
 //! ```
 //!
+//! To find out the order in which things happened to wakers, rather
+//! than just the totals, [`Wookie::enable_log`] and [`local!`]'s
+//! `log` argument keep a log of [`Event`]s. While logging, [`Wookie`]
+//! also tells its wakers apart with a [`WakerId`], so you can see
+//! whether a wake came from a stale waker. This costs `will_wake`, see
+//! [Waker identity](Wookie#waker-identity).
+//!
+//! [`Stats`] values are snapshots. Subtract an earlier one from a later
+//! one to see what happened in between, and check just the fields you
+//! care about with [`assert_stats!`]. As well as waker activity, they
+//! count polls, `Pending` results and spurious polls (ones that nothing
+//! woke), and tell `wake` from `wake_by_ref` and a future waking itself
+//! from being woken from outside (see [`WakePolicy`]):
+//!
+//! ```
+//! use wookie::{assert_stats, wookie};
+//! wookie!(future: async { true });
+//! let before = future.stats();
+//! future.poll();
+//! assert_stats!(future.stats() - before, cloned: 0, woken: 0, polls: 1; "first poll");
+//! ```
+//!
+//! The most common async bug is returning `Pending` without keeping a
+//! waker. [`Wookie::check_lost_wakeups`] and
+//! [`Local::check_lost_wakeups`] catch it as it happens, and
+//! [`Wookie::check_leaked_wakers`] and [`Local::check_leaked_wakers`]
+//! catch wakers that outlive their executor.
+//!
+//! If you would rather not write `unsafe`, [`Local::scope`] lets you
+//! poll safely, checking at the end that no wakers outlived it:
+//!
+//! ```
+//! use core::task::Poll;
+//! use wookie::Local;
+//! Local::scope(async { true }, |future| {
+//!     assert_eq!(future.poll(), Poll::Ready(true));
+//!     future.stats().assert(0, 0, 0);
+//! });
+//! ```
+//!
+//! Polling a future again after it completes (or a stream after it
+//! ends) panics, as many do not expect it. [`Wookie::allow_repoll`] turns
+//! this off for testing fused futures.
+//!
 //! For benchmarking, we provide the [`dummy!`] macro, whose waker does
 //! nothing, but quite quickly.
 //!
@@ -75,16 +119,97 @@
 //! // assert_ready!(42, Poll::Ready(420)); // would fail
 //! ```
 //!
+//! To test tasks that interact with each other, [`Multi`] drives
+//! several futures at once and tracks wakers for each:
+//!
+//! ```
+//! use core::task::Poll;
+//! use wookie::Multi;
+//! let mut tasks = Multi::new();
+//! let a = tasks.spawn(async { 1 });
+//! let b = tasks.spawn(async { 2 });
+//! assert_eq!(tasks.poll(b), Poll::Ready(2));
+//! assert_eq!(tasks.poll_while_woken(), vec![(a, 1)]);
+//! tasks.stats(a).assert(0, 0, 0);
+//! ```
+//!
+//! To find bugs that only show up when tasks are polled in a
+//! particular order, [`Explore`] reruns a set of tasks once for every
+//! possible poll order and reports the first failing one as a
+//! replayable [`Trace`]. [`Multi::with_seed`] picks a random but
+//! reproducible order instead.
+//!
+//! To check a future is cancel-safe, [`Cancel`] drops it at every point
+//! where it could be suspended in turn, checking that no wakers leak
+//! and that an invariant you give it still holds. [`yield_now`] gives
+//! the futures you test them on a suspension point.
+//!
+//! Code with timeouts can be tested against a virtual [`Clock`], whose
+//! timers only fire when you advance it:
+//!
+//! ```
+//! use core::task::Poll;
+//! use core::time::Duration;
+//! use wookie::{Clock, wookie};
+//! let clock = Clock::new();
+//! wookie!(future: clock.sleep(Duration::from_secs(5)));
+//! assert_eq!(future.poll(), Poll::Pending);
+//! clock.advance(Duration::from_secs(5));
+//! assert_eq!(future.woken(), 1);
+//! assert_eq!(future.poll(), Poll::Ready(()));
+//! ```
+//!
+//! With the `futures-core` feature, [`Wookie`], [`Local`] and [`Dummy`] can
+//! step `Stream`s too, with `poll_next`, `poll_next_while_woken` and
+//! `collect_while_woken`. [`assert_next!`] and [`assert_stream_done!`]
+//! check the results, and [`iter_stream`] makes a stream to try them on.
+//!
+//! With the `futures-sink` feature, [`Wookie`] and [`Local`] can step
+//! `Sink`s with `poll_ready`, `start_send`, `poll_flush` and
+//! `poll_close`, and `sink_stats` tells you what happened during each
+//! one, such as a full sink keeping a waker in `poll_ready`.
+//!
+//! With the `std` feature, [`MockIo`] provides scripted I/O for
+//! stepping protocol code and [`duplex`] provides an in-memory pipe
+//! for stepping a client and server against each other.
+//!
+//! With the `futures-io` or `tokio` feature, [`Wookie::futures_io`] and
+//! [`Wookie::tokio_io`] step your own readers and writers directly, one
+//! `poll_read`, `poll_write`, `poll_flush` or close at a time, so you
+//! can see which call registered which waker.
+//!
 //! ## Features
 //!
 //! Default features: `alloc`.
 //!
-//! * `alloc` - enables use of an allocator. Required by [`Wookie`] / [`wookie!`].
+//! * `alloc` - enables use of an allocator. Required by [`Wookie`] / [`wookie!`], [`Multi`] and [`Clock`].
+//! * `std` - enables use of the standard library. Implies `alloc`. Required by [`MockIo`] and [`duplex`].
+//!   Adds backtraces to leaked waker reports and [`Wookie::capture_backtraces`]. Lets [`Local`]
+//!   wakers check they stay on the thread that polled them without [`set_thread_id_hook`],
+//!   and adds `try_poll`, which catches a panicking future.
+//! * `counter-u32` - makes [`Count`], the type of the waker and poll counters, `u32` instead of `u16`.
+//! * `counter-u64` - makes [`Count`] `u64`. Takes precedence over `counter-u32`. Needs 64-bit atomics.
+//! * `futures-core` - lets [`Wookie`], [`Local`] and [`Dummy`] step `Stream`s with `poll_next`.
+//! * `futures-sink` - lets [`Wookie`] and [`Local`] step `Sink`s with `poll_ready`, `start_send`,
+//!   `poll_flush` and `poll_close`, keeping stats for each (see [`SinkPhase`]).
+//! * `futures-io` - implements the `futures-io` I/O traits for our I/O types and adds
+//!   [`FuturesIo`] for stepping yours. Implies `std`.
+//! * `tokio` - implements the `tokio` I/O traits for our I/O types and adds [`TokioIo`]
+//!   for stepping yours. Implies `std`.
 #![no_std]
+// The docs link to items behind features. docs.rs builds with all of
+// them, so only a build without them sees the links as broken.
+#![cfg_attr(
+    not(all(feature="std", feature="futures-io", feature="tokio", feature="futures-sink")),
+    allow(rustdoc::broken_intra_doc_links)
+)]
 
 #[cfg(feature="alloc")]
 extern crate alloc;
 
+#[cfg(feature="std")]
+extern crate std;
+
 mod dummy;
 #[doc(inline)]
 pub use dummy::*;
@@ -92,34 +217,68 @@ pub use dummy::*;
 mod local;
 pub use local::*;
 
+mod log;
+pub use log::*;
+
+mod check;
+pub use check::*;
+
+mod stats;
+pub use stats::*;
+
+mod thread;
+pub use thread::*;
+
+mod fixture;
+pub use fixture::*;
+
+#[cfg(feature="futures-sink")]
+mod sink;
+#[cfg(feature="futures-sink")]
+pub use sink::*;
+
+#[cfg(feature="alloc")]
+mod lock;
+
 #[cfg(feature="alloc")]
 mod wookie;
 #[cfg(feature="alloc")]
 pub use crate::wookie::*;
 
-/// Statistics of waker activity for [`Wookie`] or [`Local`].
-pub struct Stats {
-    /// The number of times a Waker has been cloned. Usually equivalent to the
-    /// number of times a waker has been set.
-    pub cloned:  u16,
-    /// The number of times a Waker has been dropped. Note that `wake` causes
-    /// this count to be incremented as it takes ownership of the Waker.
-    pub dropped: u16,
-    /// The number of times a Waker has been woken. Includes calls to both
-    /// `wake` and `wake_by_ref`.
-    pub woken:   u16,
-}
+#[cfg(feature="alloc")]
+mod multi;
+#[cfg(feature="alloc")]
+pub use multi::*;
 
-impl Stats {
-    /// The number of live wakers, i.e. `cloned - dropped`.
-    #[inline(always)]
-    pub fn live(&self) -> u16 { self.cloned - self.dropped }
+#[cfg(feature="alloc")]
+mod explore;
+#[cfg(feature="alloc")]
+pub use explore::*;
 
-    /// Assert that `cloned`, `dropped` and `woken` are the provided values.
-    pub fn assert(&self, cloned: u16, dropped: u16, woken: u16) {
-        assert_eq!((cloned, dropped, woken), (self.cloned, self.dropped, self.woken));
-    }
-}
+#[cfg(feature="alloc")]
+mod cancel;
+#[cfg(feature="alloc")]
+pub use cancel::*;
+
+#[cfg(feature="alloc")]
+mod time;
+#[cfg(feature="alloc")]
+pub use time::*;
+
+#[cfg(any(feature="futures-io", feature="tokio"))]
+mod io;
+#[cfg(any(feature="futures-io", feature="tokio"))]
+pub use io::*;
+
+#[cfg(feature="std")]
+mod mock;
+#[cfg(feature="std")]
+pub use mock::*;
+
+#[cfg(feature="std")]
+mod duplex;
+#[cfg(feature="std")]
+pub use duplex::*;
 
 #[macro_export]
 /// Asserts that a [`Poll`] is a [`Poll::Pending`]
@@ -172,3 +331,60 @@ macro_rules! assert_ready {
         }
     }
 }
+
+#[macro_export]
+/// Asserts that a stream's `Poll<Option<T>>` is a `Poll::Ready` with
+/// a next item.
+///
+/// ## Examples
+///
+/// ```
+/// use wookie::assert_next;
+/// use core::task::Poll;
+///
+/// // With 1 arg, returns the item.
+/// assert_eq!(42, assert_next!(Poll::Ready(Some(42))));
+/// // assert_next!(Poll::<Option<i32>>::Ready(None)); // would fail
+///
+/// // With 2 args, it's like [`assert_eq`] on the item.
+/// assert_next!(42, Poll::Ready(Some(42)));
+/// // assert_next!(42, Poll::<Option<i32>>::Pending); // would fail
+/// ```
+macro_rules! assert_next {
+    ($expr:expr) => {
+        match $expr {
+            Poll::Ready(Some(r)) => r,
+            Poll::Ready(None) => panic!("Expected a next item, got the end of the stream!"),
+            Poll::Pending => panic!("Expected a next item, got Poll::Pending!"),
+        }
+    };
+    ($expected:expr, $expr:expr) => {
+        match $expr {
+            Poll::Ready(Some(r)) => assert_eq!($expected, r),
+            Poll::Ready(None) => panic!("Expected a next item, got the end of the stream!"),
+            Poll::Pending => panic!("Expected a next item, got Poll::Pending!"),
+        }
+    }
+}
+
+#[macro_export]
+/// Asserts that a stream's `Poll<Option<T>>` is `Poll::Ready(None)`,
+/// i.e. the stream has ended.
+///
+/// ## Examples
+///
+/// ```
+/// use wookie::assert_stream_done;
+/// use core::task::Poll;
+/// assert_stream_done!(Poll::<Option<i32>>::Ready(None)); // pass
+/// // assert_stream_done!(Poll::Ready(Some(42))); // would fail
+/// ```
+macro_rules! assert_stream_done {
+    ($expr:expr) => {
+        match $expr {
+            Poll::Ready(None) => {}
+            Poll::Ready(Some(r)) => panic!("Expected the end of the stream, got item {:?}!", r),
+            Poll::Pending => panic!("Expected the end of the stream, got Poll::Pending!"),
+        }
+    }
+}
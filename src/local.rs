@@ -1,10 +1,24 @@
 use crate::*;
-use core::cell::Cell;
+use core::cell::{Cell, RefCell};
 use core::future::Future;
-#[cfg(feature="alloc")]
+use core::marker::PhantomPinned;
 use core::mem::ManuallyDrop;
 use core::pin::Pin;
 use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use crate::check::abort;
+use crate::thread::Owner;
+#[cfg(feature="alloc")]
+use core::time::Duration;
+#[cfg(feature="futures-core")]
+use futures_core::Stream;
+#[cfg(feature="futures-sink")]
+use crate::sink::SinkStats;
+#[cfg(feature="futures-sink")]
+use futures_sink::Sink;
+#[cfg(all(feature="futures-core", feature="alloc"))]
+use alloc::vec::Vec;
+#[cfg(feature="std")]
+use crate::check::catch_poll;
 
 /// Wraps a future in a single-stepping executor that tracks wakers
 /// and pins it on the stack.
@@ -12,6 +26,14 @@ use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 /// Unlike [`wookie!`], does not require a global allocator at
 /// the cost of unsafe polling.
 ///
+/// In debug builds, a waker used after its `Local` has been dropped
+/// will usually abort the process with a message rather than quietly
+/// corrupt memory.
+///
+/// Our wakers are not thread safe, so they panic if used on any thread
+/// other than the one that last polled or dropped the `Local`. This
+/// needs the `std` feature or [`set_thread_id_hook`].
+///
 /// ## Examples
 ///
 /// ```
@@ -32,8 +54,28 @@ use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 /// // or equivalently...
 /// future.stats().assert(0, 0, 0);
 /// ```
+///
+/// You can also keep a log of the last few waker events:
+///
+/// ```
+/// use core::task::Poll;
+/// use wookie::local;
+/// local!(future: async { true }, log: 16);
+/// assert_eq!(unsafe { future.poll() }, Poll::Ready(true));
+/// future.assert_events(&[]);
+/// ```
 #[macro_export]
 macro_rules! local {
+    ($name:ident, log: $log:expr) => {
+        let mut $name = unsafe { $crate::Local::with_log::<{ $log }>($name) };
+        #[allow(unused_mut)]
+        let mut $name = unsafe { core::pin::Pin::new_unchecked(&mut $name) };
+    };
+    ($name:ident : $future:expr, log: $log:expr) => {
+        let mut $name = unsafe { $crate::Local::with_log::<{ $log }>($future) };
+        #[allow(unused_mut)]
+        let mut $name = unsafe { core::pin::Pin::new_unchecked(&mut $name) };
+    };
     ($name:ident) => {
         let mut $name = unsafe { $crate::Local::new($name) };
         #[allow(unused_mut)]
@@ -72,31 +114,120 @@ macro_rules! local {
 /// // or equivalently...
 /// future.stats().assert(0, 0, 0);
 /// ```
-pub struct Local<F> {
-    wakey: Wakey,
-    future: F,
+///
+/// Our wakers point into the [`Local`], so once pinned it cannot be
+/// moved, even if the future could be:
+///
+/// ```compile_fail,E0277
+/// use core::future::ready;
+/// use wookie::local;
+/// local!(future: ready(1));
+/// local!(other: ready(2));
+/// core::mem::swap(future.as_mut().get_mut(), other.as_mut().get_mut());
+/// ```
+pub struct Local<F, const LOG: usize = 0> {
+    wakey: Wakey<LOG>,
+    lost_wakeups: Check,
+    lost_wakeup: Option<LostWakeup>,
+    check_leaks: bool,
+    #[cfg(feature="futures-sink")]
+    sink: SinkStats,
+    #[cfg(feature="std")]
+    poisoned: bool,
+    terminated: bool,
+    repoll: bool,
+    future: ManuallyDrop<F>,
+    // Our wakers point into us, so we must never move, even if `F` can.
+    _pinned: PhantomPinned,
 }
 
-impl<F: Future> Local<F> {
+impl<F> Local<F> {
     /// Creates a new [`Local`] without pinning it to the stack. You
     /// probably want the [`local!`] macro.
     #[inline(always)]
-    pub fn new(future: F) -> Local<F> {
-        let wakey = Wakey::default();
-        Local { wakey, future }
+    pub fn new(future: F) -> Local<F> { Local::with_log::<0>(future) }
+
+    /// Creates a new [`Local`] that keeps a log of the last `LOG`
+    /// waker events without pinning it to the stack. You probably want
+    /// the [`local!`] macro.
+    #[inline(always)]
+    pub fn with_log<const LOG: usize>(future: F) -> Local<F, LOG> {
+        Local {
+            wakey: Wakey::default(),
+            lost_wakeups: Check::Off,
+            lost_wakeup: None,
+            check_leaks: false,
+            #[cfg(feature="futures-sink")]
+            sink: SinkStats::default(),
+            #[cfg(feature="std")]
+            poisoned: false,
+            terminated: false,
+            repoll: false,
+            future: ManuallyDrop::new(future),
+            _pinned: PhantomPinned,
+        }
     }
 
+    /// Runs `f` with a [`Scoped`], which can poll the future safely.
+    ///
+    /// When `f` returns (or panics), the future is dropped and we check
+    /// that no wakers are still alive. If any are, we abort the process
+    /// rather than let them point to memory that is no longer valid.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use wookie::Local;
+    /// let answer = Local::scope(async { 42 }, |future| {
+    ///     let answer = future.poll();
+    ///     future.stats().assert(0, 0, 0);
+    ///     answer
+    /// });
+    /// assert_eq!(answer, Poll::Ready(42));
+    /// ```
+    #[inline(always)]
+    pub fn scope<R>(future: F, f: impl FnOnce(&mut Scoped<'_, F>) -> R) -> R {
+        Local::new(future).enter(f)
+    }
+
+    /// Like [`Local::scope`], but keeps a log of the last `LOG` waker
+    /// events.
+    #[inline(always)]
+    pub fn scope_with_log<const LOG: usize, R>(
+        future: F,
+        f: impl FnOnce(&mut Scoped<'_, F, LOG>) -> R,
+    ) -> R {
+        Local::with_log::<LOG>(future).enter(f)
+    }
+}
+
+impl<F, const LOG: usize> Local<F, LOG> {
+    fn enter<R>(self, f: impl FnOnce(&mut Scoped<'_, F, LOG>) -> R) -> R {
+        // We drop the future ourselves and the rest has no drop glue.
+        let mut local = ManuallyDrop::new(self);
+        let guard = ScopeGuard(&mut *local);
+        // Safety: `local` is never moved and `guard` does not touch it
+        // until we are done with this.
+        let local = unsafe { Pin::new_unchecked(&mut *guard.0) };
+        let ret = f(&mut Scoped { local });
+        drop(guard);
+        ret
+    }
+}
+
+impl<F, const LOG: usize> Local<F, LOG> {
     /// Returns how many times the waker has been woken. This count is
     /// cumulative, it is never reset and is allowed to overflow.
     #[inline(always)]
-    pub fn woken(self: &mut Pin<&mut Self>) -> u16 {
+    pub fn woken(self: &mut Pin<&mut Self>) -> Count {
         self.as_mut().project().wakey.woken.get()
     }
 
     /// Returns how many times the waker has been cloned. This count is
     /// cumulative, it is never reset and is allowed to overflow.
     #[inline(always)]
-    pub fn cloned(self: &mut Pin<&mut Self>) -> u16 {
+    pub fn cloned(self: &mut Pin<&mut Self>) -> Count {
         self.as_mut().project().wakey.cloned.get()
     }
 
@@ -104,29 +235,156 @@ impl<F: Future> Local<F> {
     /// dropped. This count is cumulative, it is never reset and is
     /// allowed to overflow.
     #[inline(always)]
-    pub fn dropped(self: &mut Pin<&mut Self>) -> u16 {
+    pub fn dropped(self: &mut Pin<&mut Self>) -> Count {
         self.as_mut().project().wakey.dropped.get()
     }
 
     /// Returns statistics about use of our wakers.
     #[inline(always)]
     pub fn stats(self: &mut Pin<&mut Self>) -> Stats {
-        let wakey = &self.as_mut().project().wakey;
-        Stats {
-            cloned:  wakey.cloned.get(),
-            dropped: wakey.dropped.get(),
-            woken:   wakey.woken.get(),
-        }
+        self.as_mut().project().wakey.stats()
     }
     /// Returns how many times a clone of the waker has been
     /// dropped. This count is cumulative, it is never reset and is
     /// allowed to overflow.
     #[inline(always)]
-    pub fn live(self: &mut Pin<&mut Self>) -> u16 {
-        let wakey = &self.as_mut().project().wakey;
-        wakey.cloned.get() - wakey.dropped.get()
+    pub fn live(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.stats().live()
     }
 
+    /// Returns how many times the future has been polled. This count
+    /// is cumulative, it is never reset and is allowed to overflow.
+    #[inline(always)]
+    pub fn polls(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.polls.get()
+    }
+
+    /// Returns how many polls of the future returned `Pending`.
+    #[inline(always)]
+    pub fn pending(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.pending.get()
+    }
+
+    /// Returns how many polls were not preceded by a wake since the
+    /// previous poll began.
+    #[inline(always)]
+    pub fn spurious(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.spurious.get()
+    }
+
+    /// Checks for lost wakeups after each poll: a poll that returns
+    /// `Pending` with no live wakers that did not wake the future
+    /// either, so nothing can ever wake it again.
+    ///
+    /// With [`Check::Record`], the most recent one can be retrieved
+    /// with [`Local::take_lost_wakeup`].
+    ///
+    /// ## Example
+    ///
+    /// ```should_panic
+    /// use wookie::{Check, local};
+    /// local!(future: core::future::pending::<()>());
+    /// future.check_lost_wakeups(Check::Panic);
+    /// unsafe { future.poll() }; // panics: nobody kept the waker.
+    /// ```
+    #[inline(always)]
+    pub fn check_lost_wakeups(self: &mut Pin<&mut Self>, mode: Check) {
+        self.as_mut().project().lost_wakeups = mode;
+    }
+
+    /// Returns the most recent lost wakeup recorded by
+    /// [`Local::check_lost_wakeups`], if any, and forgets it.
+    #[inline(always)]
+    pub fn take_lost_wakeup(self: &mut Pin<&mut Self>) -> Option<LostWakeup> {
+        self.as_mut().project().lost_wakeup.take()
+    }
+
+    /// Checks that no wakers are still alive when the [`Local`] is
+    /// dropped, after dropping the future. A waker that outlives its
+    /// [`Local`] points to memory that is no longer valid.
+    ///
+    /// Unlike [`Wookie::check_leaked_wakers`], cannot tell you where
+    /// the leaked wakers were cloned.
+    ///
+    /// ## Panics
+    ///
+    /// On drop, if any wakers are still alive, unless the thread is
+    /// already panicking (only detectable with the `std` feature).
+    #[inline(always)]
+    pub fn check_leaked_wakers(self: &mut Pin<&mut Self>, enabled: bool) {
+        self.as_mut().project().check_leaks = enabled;
+    }
+
+    /// Returns whether the future has completed or the stream has
+    /// ended.
+    #[inline(always)]
+    pub fn is_terminated(self: &mut Pin<&mut Self>) -> bool {
+        self.as_mut().project().terminated
+    }
+
+    /// Allows polling again after the future has completed or the
+    /// stream has ended, for testing fused futures and streams. By
+    /// default, this panics, as many futures do not expect it.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::local;
+    /// local!(future: poll_fn(|_| Poll::Ready(())));
+    /// assert_eq!(unsafe { future.poll() }, Poll::Ready(()));
+    /// assert!(future.is_terminated());
+    /// future.allow_repoll(true);
+    /// assert_eq!(unsafe { future.poll() }, Poll::Ready(()));
+    /// ```
+    #[inline(always)]
+    pub fn allow_repoll(self: &mut Pin<&mut Self>, allowed: bool) {
+        self.as_mut().project().repoll = allowed;
+    }
+
+    /// Returns whether the future panicked in [`Local::try_poll`]. A
+    /// poisoned local panics if polled again.
+    #[cfg(feature="std")]
+    #[inline(always)]
+    pub fn is_poisoned(self: &mut Pin<&mut Self>) -> bool {
+        self.as_mut().project().poisoned
+    }
+
+    /// Returns a copy of the log of the last `LOG` clones, wakes and
+    /// drops of our wakers, tagged with the poll they happened during.
+    /// Always empty unless created with a log size.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::{EventKind::*, local};
+    /// local!(future: poll_fn(|ctx| {
+    ///     ctx.waker().wake_by_ref();
+    ///     Poll::<()>::Pending
+    /// }), log: 1);
+    /// assert_eq!(unsafe { future.poll() }, Poll::Pending);
+    /// assert_eq!(unsafe { future.poll() }, Poll::Pending);
+    /// let events = future.events();
+    /// events.assert(&[WakeByRef.during(2)]);
+    /// assert_eq!(events.overwritten(), 1);
+    /// ```
+    #[inline(always)]
+    pub fn events(self: &mut Pin<&mut Self>) -> RingLog<LOG> {
+        *self.as_mut().project().wakey.log.borrow()
+    }
+
+    /// Asserts that the events in the log are exactly `expected`.
+    #[track_caller]
+    pub fn assert_events(self: &mut Pin<&mut Self>, expected: &[Event]) {
+        self.events().assert(expected)
+    }
+
+}
+
+impl<F: Future, const LOG: usize> Local<F, LOG> {
     /// Polls the contained future once.
     ///
     /// ## Example
@@ -146,11 +404,41 @@ impl<F: Future> Local<F> {
     pub unsafe fn poll(
         self: &mut Pin<&mut Self>
     ) -> Poll<<F as Future>::Output> {
-        let this = self.as_mut().project();
-        let waker = ManuallyDrop::new(this.waker());
-        let future = Pin::new_unchecked(&mut this.future);
-        let mut ctx = Context::from_waker(&waker);
-        Future::poll(future, &mut ctx)
+        let ret = self.step(Future::poll);
+        self.terminate_if(ret.is_ready());
+        ret
+    }
+
+    /// Polls the contained future once, catching a panic instead of
+    /// letting it unwind through the test.
+    ///
+    /// If the future panics, the local is poisoned. Its stats can
+    /// still be read, but polling it again panics.
+    ///
+    /// ## Panics
+    ///
+    /// Like [`Local::poll`], if the local is poisoned or the future has
+    /// completed. These are not caught.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the future is polled with to
+    /// exist longer than `self`.
+    #[cfg(feature="std")]
+    pub unsafe fn try_poll(
+        self: &mut Pin<&mut Self>
+    ) -> Result<Poll<<F as Future>::Output>, PanicPayload> {
+        match self.step(catch_poll) {
+            Poll::Ready(Ok(out)) => {
+                self.terminate_if(true);
+                Ok(Poll::Ready(out))
+            }
+            Poll::Ready(Err(payload)) => {
+                self.as_mut().project().poisoned = true;
+                Err(payload)
+            }
+            Poll::Pending => Ok(Poll::Pending),
+        }
     }
 
     /// Polls the contained future to completion, so long as the
@@ -173,21 +461,137 @@ impl<F: Future> Local<F> {
     pub unsafe fn poll_while_woken(
         self: &mut Pin<&mut Self>
     ) -> Poll<<F as Future>::Output> {
-        let mut woken = self.woken();
+        self.poll_while_woken_by(WakePolicy::Any)
+    }
+
+    /// Like [`Local::poll_while_woken`], but only polls again if the
+    /// previous poll caused wakes that count under `policy`.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the future is polled with to
+    /// exist longer than `self`.
+    pub unsafe fn poll_while_woken_by(
+        self: &mut Pin<&mut Self>,
+        policy: WakePolicy,
+    ) -> Poll<<F as Future>::Output> {
+        let ret = self.step_while_woken(policy, Future::poll);
+        self.terminate_if(ret.is_ready());
+        ret
+    }
+
+    /// Like [`Local::poll_while_woken`], but whenever the future stops
+    /// being woken, moves the [`Clock`] forward to the next timer
+    /// deadline, so timeouts fire instantly. Stops when the future
+    /// completes or there are no timers left. Returns the virtual time
+    /// that passed along with the result.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use core::time::Duration;
+    /// use wookie::{Clock, local};
+    /// let clock = Clock::new();
+    /// local!(future: clock.sleep(Duration::from_secs(60)));
+    /// let (poll, elapsed) = unsafe { future.run_until_stalled_with_time(&clock) };
+    /// assert_eq!(poll, Poll::Ready(()));
+    /// assert_eq!(elapsed, Duration::from_secs(60));
+    /// ```
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the future is polled with to
+    /// exist longer than `self`.
+    #[cfg(feature="alloc")]
+    pub unsafe fn run_until_stalled_with_time(
+        self: &mut Pin<&mut Self>,
+        clock: &Clock,
+    ) -> (Poll<<F as Future>::Output>, Duration) {
+        let start = clock.now();
         loop {
-            if let Poll::Ready(r) = self.poll() { return Poll::Ready(r); }
-            let w = self.woken();
+            if let Poll::Ready(r) = self.poll_while_woken() {
+                return (Poll::Ready(r), clock.now() - start);
+            }
+            let woken = self.woken();
+            while self.woken() == woken {
+                match clock.next_deadline() {
+                    Some(deadline) => { clock.advance_to(deadline); }
+                    None => return (Poll::Pending, clock.now() - start),
+                }
+            }
+        }
+    }
+
+}
+
+impl<F, const LOG: usize> Local<F, LOG> {
+    /// Runs `f` with [`Local::step`] until it is ready, so long as the
+    /// previous run caused wakes that count under `policy`.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the future is polled with to
+    /// exist longer than `self`.
+    unsafe fn step_while_woken<R>(
+        self: &mut Pin<&mut Self>,
+        policy: WakePolicy,
+        mut f: impl FnMut(Pin<&mut F>, &mut Context) -> Poll<R>,
+    ) -> Poll<R> {
+        let mut woken = policy.count(&self.stats());
+        loop {
+            if let Poll::Ready(r) = self.step(&mut f) { return Poll::Ready(r); }
+            let w = policy.count(&self.stats());
             if w == woken { return Poll::Pending; }
             woken = w;
         }
     }
 
+    /// Remembers that the future has completed or the stream has
+    /// ended if `done`.
+    #[inline(always)]
+    fn terminate_if(self: &mut Pin<&mut Self>, done: bool) {
+        if done { self.as_mut().project().terminated = true; }
+    }
+
+    /// Runs one poll of the future with our waker.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the future is polled with to
+    /// exist longer than `self`.
+    #[inline(always)]
+    unsafe fn step<R>(
+        self: &mut Pin<&mut Self>,
+        f: impl FnOnce(Pin<&mut F>, &mut Context) -> Poll<R>,
+    ) -> Poll<R> {
+        let this = self.as_mut().project();
+        #[cfg(feature="std")]
+        assert!(!this.poisoned, "Local polled again after its future panicked");
+        assert!(
+            !this.terminated || this.repoll,
+            "Local polled again after it completed. Use allow_repoll to allow this."
+        );
+        let woken = this.wakey.woken.get();
+        let poll = this.wakey.begin_poll();
+        let waker = ManuallyDrop::new(this.waker());
+        let future = Pin::new_unchecked(&mut *this.future);
+        let mut ctx = Context::from_waker(&waker);
+        let ret = f(future, &mut ctx);
+        this.wakey.end_poll(ret.is_pending());
+        if this.lost_wakeups != Check::Off {
+            let lost = LostWakeup::check(poll, ret.is_pending(), woken, this.wakey.stats());
+            LostWakeup::handle(this.lost_wakeups, lost, &mut this.lost_wakeup);
+        }
+        ret
+    }
+
     #[inline(always)]
     fn waker(&self) -> Waker {
         // Safety: the returned waker is valid as long as self is
         // valid. But in order to do anything mutable with the Waker,
         // they would have to have cloned it first.
-        let raw = raw_waker(&self.wakey as *const Wakey);
+        let raw = raw_waker(&self.wakey as *const Wakey<LOG>);
         unsafe { Waker::from_raw(raw) }
     }
 
@@ -195,43 +599,529 @@ impl<F: Future> Local<F> {
     fn project(self: Pin<&mut Self>) -> &mut Self {
         unsafe { Pin::into_inner_unchecked(self) }
     }
+}
+
+#[cfg(feature="futures-core")]
+impl<F: Stream, const LOG: usize> Local<F, LOG> {
+    /// Polls the contained stream for its next item once.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the stream is polled with to
+    /// exist longer than `self`.
+    #[inline(always)]
+    pub unsafe fn poll_next(self: &mut Pin<&mut Self>) -> Poll<Option<F::Item>> {
+        let ret = self.step(Stream::poll_next);
+        self.terminate_if(matches!(ret, Poll::Ready(None)));
+        ret
+    }
+
+    /// Polls the contained stream for its next item until it gets one
+    /// or the stream ends, so long as the previous poll caused one or
+    /// more wakes.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the stream is polled with to
+    /// exist longer than `self`.
+    #[inline(always)]
+    pub unsafe fn poll_next_while_woken(self: &mut Pin<&mut Self>) -> Poll<Option<F::Item>> {
+        let ret = self.step_while_woken(WakePolicy::Any, Stream::poll_next);
+        self.terminate_if(matches!(ret, Poll::Ready(None)));
+        ret
+    }
+
+    /// Collects items from the contained stream for as long as they
+    /// are available, as with [`Local::poll_next_while_woken`].
+    /// Also returns whether the stream has ended.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the stream is polled with to
+    /// exist longer than `self`.
+    #[cfg(feature="alloc")]
+    pub unsafe fn collect_while_woken(self: &mut Pin<&mut Self>) -> (Vec<F::Item>, Poll<()>) {
+        let mut items = Vec::new();
+        loop {
+            match self.poll_next_while_woken() {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return (items, Poll::Ready(())),
+                Poll::Pending => return (items, Poll::Pending),
+            }
+        }
+    }
+}
+
+#[cfg(feature="futures-sink")]
+impl<F, const LOG: usize> Local<F, LOG> {
+    /// Polls the contained sink's `poll_ready` once.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the sink is polled with to
+    /// exist longer than `self`.
+    #[inline(always)]
+    pub unsafe fn poll_ready<I>(self: &mut Pin<&mut Self>) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Ready);
+        self.step(Sink::poll_ready)
+    }
+
+    /// Calls the contained sink's `start_send`.
+    #[inline(always)]
+    pub fn start_send<I>(self: &mut Pin<&mut Self>, item: I) -> Result<(), <F as Sink<I>>::Error>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Send);
+        let this = self.as_mut().project();
+        Sink::start_send(unsafe { Pin::new_unchecked(&mut *this.future) }, item)
+    }
+
+    /// Polls the contained sink's `poll_flush` once.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the sink is polled with to
+    /// exist longer than `self`.
+    #[inline(always)]
+    pub unsafe fn poll_flush<I>(self: &mut Pin<&mut Self>) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Flush);
+        self.step(Sink::poll_flush)
+    }
+
+    /// Polls the contained sink's `poll_close` once.
+    ///
+    /// ## Safety
+    ///
+    /// You must not allow the Waker the sink is polled with to
+    /// exist longer than `self`.
+    #[inline(always)]
+    pub unsafe fn poll_close<I>(self: &mut Pin<&mut Self>) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Close);
+        self.step(Sink::poll_close)
+    }
+
+    /// Returns the waker and poll activity put down to one of the
+    /// sink methods. See [`SinkPhase`].
+    #[inline(always)]
+    pub fn sink_stats(self: &mut Pin<&mut Self>, phase: SinkPhase) -> Stats {
+        let now = self.stats();
+        self.as_mut().project().sink.get(phase, now)
+    }
+
+    #[inline(always)]
+    fn enter_sink(self: &mut Pin<&mut Self>, phase: SinkPhase) {
+        let now = self.stats();
+        self.as_mut().project().sink.enter(phase, now)
+    }
+}
+
+/// Safe access to a [`Local`] inside [`Local::scope`].
+///
+/// Has safe versions of the polling methods of [`Local`] and forwards
+/// the rest. It does not give out the [`Local`] itself, which could
+/// then be replaced with one whose wakers the scope does not check.
+pub struct Scoped<'s, F, const LOG: usize = 0> {
+    local: Pin<&'s mut Local<F, LOG>>,
+}
+
+impl<'s, F: Future, const LOG: usize> Scoped<'s, F, LOG> {
+    /// Polls the contained future once.
+    #[inline(always)]
+    pub fn poll(&mut self) -> Poll<<F as Future>::Output> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll() }
+    }
+
+    /// Polls the contained future once, catching a panic. See
+    /// [`Local::try_poll`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use wookie::Local;
+    /// Local::scope(async { panic!("oops") }, |future| {
+    ///     assert!(future.try_poll().is_err());
+    ///     assert!(future.is_poisoned());
+    ///     future.stats().assert(0, 0, 0);
+    /// });
+    /// ```
+    #[cfg(feature="std")]
+    #[inline(always)]
+    pub fn try_poll(&mut self) -> Result<Poll<<F as Future>::Output>, PanicPayload> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.try_poll() }
+    }
+
+    /// Polls the contained future to completion, so long as the
+    /// previous poll caused one or more wakes.
+    #[inline(always)]
+    pub fn poll_while_woken(&mut self) -> Poll<<F as Future>::Output> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll_while_woken() }
+    }
 
+    /// Like [`Scoped::poll_while_woken`], but only polls again if the
+    /// previous poll caused wakes that count under `policy`.
+    #[inline(always)]
+    pub fn poll_while_woken_by(&mut self, policy: WakePolicy) -> Poll<<F as Future>::Output> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll_while_woken_by(policy) }
+    }
+
+    /// Like [`Scoped::poll_while_woken`], but advances the [`Clock`]
+    /// whenever the future stops being woken. See
+    /// [`Local::run_until_stalled_with_time`].
+    #[cfg(feature="alloc")]
+    #[inline(always)]
+    pub fn run_until_stalled_with_time(
+        &mut self,
+        clock: &Clock,
+    ) -> (Poll<<F as Future>::Output>, Duration) {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.run_until_stalled_with_time(clock) }
+    }
+}
+
+#[cfg(feature="futures-core")]
+impl<'s, F: Stream, const LOG: usize> Scoped<'s, F, LOG> {
+    /// Polls the contained stream for its next item once.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use wookie::{Local, assert_next, assert_stream_done, iter_stream};
+    /// Local::scope(iter_stream(1..2), |stream| {
+    ///     assert_next!(1, stream.poll_next());
+    ///     assert_stream_done!(stream.poll_next());
+    /// });
+    /// ```
+    #[inline(always)]
+    pub fn poll_next(&mut self) -> Poll<Option<F::Item>> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll_next() }
+    }
+
+    /// Polls the contained stream for its next item until it gets one
+    /// or the stream ends, so long as the previous poll caused one or
+    /// more wakes.
+    #[inline(always)]
+    pub fn poll_next_while_woken(&mut self) -> Poll<Option<F::Item>> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll_next_while_woken() }
+    }
+
+    /// Collects items from the contained stream for as long as they
+    /// are available. See [`Local::collect_while_woken`].
+    #[cfg(feature="alloc")]
+    #[inline(always)]
+    pub fn collect_while_woken(&mut self) -> (Vec<F::Item>, Poll<()>) {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.collect_while_woken() }
+    }
+}
+
+#[cfg(feature="futures-sink")]
+impl<'s, F, const LOG: usize> Scoped<'s, F, LOG> {
+    /// Polls the contained sink's `poll_ready` once.
+    #[inline(always)]
+    pub fn poll_ready<I>(&mut self) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll_ready() }
+    }
+
+    /// Polls the contained sink's `poll_flush` once.
+    #[inline(always)]
+    pub fn poll_flush<I>(&mut self) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll_flush() }
+    }
+
+    /// Polls the contained sink's `poll_close` once.
+    #[inline(always)]
+    pub fn poll_close<I>(&mut self) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        // Safety: the scope checks no wakers outlive it.
+        unsafe { self.local.poll_close() }
+    }
+
+    /// See [`Local::start_send`].
+    #[inline(always)]
+    pub fn start_send<I>(&mut self, item: I) -> Result<(), <F as Sink<I>>::Error>
+    where F: Sink<I> {
+        self.local.start_send(item)
+    }
+
+    /// See [`Local::sink_stats`].
+    #[inline(always)]
+    pub fn sink_stats(&mut self, phase: SinkPhase) -> Stats { self.local.sink_stats(phase) }
+}
+
+impl<'s, F, const LOG: usize> Scoped<'s, F, LOG> {
+    /// See [`Local::woken`].
+    #[inline(always)]
+    pub fn woken(&mut self) -> Count { self.local.woken() }
+
+    /// See [`Local::cloned`].
+    #[inline(always)]
+    pub fn cloned(&mut self) -> Count { self.local.cloned() }
+
+    /// See [`Local::dropped`].
+    #[inline(always)]
+    pub fn dropped(&mut self) -> Count { self.local.dropped() }
+
+    /// See [`Local::stats`].
+    #[inline(always)]
+    pub fn stats(&mut self) -> Stats { self.local.stats() }
+
+    /// See [`Local::live`].
+    #[inline(always)]
+    pub fn live(&mut self) -> Count { self.local.live() }
+
+    /// See [`Local::polls`].
+    #[inline(always)]
+    pub fn polls(&mut self) -> Count { self.local.polls() }
+
+    /// See [`Local::pending`].
+    #[inline(always)]
+    pub fn pending(&mut self) -> Count { self.local.pending() }
+
+    /// See [`Local::spurious`].
+    #[inline(always)]
+    pub fn spurious(&mut self) -> Count { self.local.spurious() }
+
+    /// See [`Local::check_lost_wakeups`].
+    #[inline(always)]
+    pub fn check_lost_wakeups(&mut self, mode: Check) { self.local.check_lost_wakeups(mode) }
+
+    /// See [`Local::take_lost_wakeup`].
+    #[inline(always)]
+    pub fn take_lost_wakeup(&mut self) -> Option<LostWakeup> { self.local.take_lost_wakeup() }
+
+    /// See [`Local::check_leaked_wakers`].
+    #[inline(always)]
+    pub fn check_leaked_wakers(&mut self, enabled: bool) { self.local.check_leaked_wakers(enabled) }
+
+    /// See [`Local::is_terminated`].
+    #[inline(always)]
+    pub fn is_terminated(&mut self) -> bool { self.local.is_terminated() }
+
+    /// See [`Local::allow_repoll`].
+    #[inline(always)]
+    pub fn allow_repoll(&mut self, allowed: bool) { self.local.allow_repoll(allowed) }
+
+    /// See [`Local::is_poisoned`].
+    #[cfg(feature="std")]
+    #[inline(always)]
+    pub fn is_poisoned(&mut self) -> bool { self.local.is_poisoned() }
+
+    /// See [`Local::events`].
+    #[inline(always)]
+    pub fn events(&mut self) -> RingLog<LOG> { self.local.events() }
+
+    /// See [`Local::assert_events`].
+    #[track_caller]
+    pub fn assert_events(&mut self, expected: &[Event]) { self.local.assert_events(expected) }
+}
+
+/// Drops the future of a scoped [`Local`], then aborts if any wakers
+/// are still alive. Runs during unwinding too.
+struct ScopeGuard<F, const LOG: usize>(*mut Local<F, LOG>);
+
+impl<F, const LOG: usize> Drop for ScopeGuard<F, LOG> {
+    fn drop(&mut self) {
+        struct Verify<'a, const LOG: usize>(&'a Wakey<LOG>);
+        struct Poison<'a, const LOG: usize>(&'a Wakey<LOG>);
+        impl<'a, const LOG: usize> Drop for Poison<'a, LOG> {
+            fn drop(&mut self) { self.0.canary.poison() }
+        }
+        impl<'a, const LOG: usize> Drop for Verify<'a, LOG> {
+            fn drop(&mut self) {
+                let stats = self.0.stats();
+                if stats.live() != 0 {
+                    abort(format_args!(
+                        "Local::scope ended with {} live wakers ({}). \
+                         Aborting, as they point to memory that is about to become invalid.",
+                        stats.live(), stats
+                    ));
+                }
+            }
+        }
+        // Safety: nothing else uses the `Local` any more.
+        let local = unsafe { &mut *self.0 };
+        let _verify = Verify(&local.wakey);
+        let _poison = Poison(&local.wakey);
+        unsafe { ManuallyDrop::drop(&mut local.future) };
+    }
+}
+
+impl<F, const LOG: usize> Drop for Local<F, LOG> {
+    fn drop(&mut self) {
+        // We may have been sent to another thread since the last poll.
+        self.wakey.owner.claim();
+        // Safety: we never touch it again.
+        unsafe { ManuallyDrop::drop(&mut self.future) };
+        self.wakey.canary.poison();
+        if self.check_leaks { self.wakey.check_leaks() }
+    }
 }
 
 #[derive(Default)]
-struct Wakey {
-    cloned:  Cell<u16>,
-    dropped: Cell<u16>,
-    woken:   Cell<u16>,
+struct Wakey<const LOG: usize> {
+    cloned:       Cell<Count>,
+    dropped:      Cell<Count>,
+    woken:        Cell<Count>,
+    woken_by_ref: Cell<Count>,
+    self_woken:   Cell<Count>,
+    polls:        Cell<Count>,
+    pending:      Cell<Count>,
+    spurious:     Cell<Count>,
+    /// `woken` as of the start of the last poll.
+    seen:         Cell<Count>,
+    polling:      Cell<bool>,
+    log:          RefCell<RingLog<LOG>>,
+    owner:        Owner,
+    canary:       Canary,
+}
+
+/// In debug builds, a marker that is overwritten when the [`Local`]
+/// is dropped, so a waker used after that finds out about it instead
+/// of corrupting memory. This is best effort: the memory may have been
+/// reused by then.
+struct Canary(#[cfg(debug_assertions)] Cell<u32>);
+
+#[cfg(debug_assertions)]
+const ALIVE: u32 = 0x600d_f00d;
+#[cfg(debug_assertions)]
+const DEAD:  u32 = 0xdead_f00d;
+
+// Only derivable in release builds, where it is empty.
+#[allow(clippy::derivable_impls)]
+impl Default for Canary {
+    fn default() -> Self { Canary(#[cfg(debug_assertions)] Cell::new(ALIVE)) }
 }
 
-impl Wakey {
-    fn bump_cloned(&self)  { self.cloned.set(self.cloned.get() + 1) }
-    fn bump_woken(&self)   { self.woken.set(self.woken.get() + 1) }
-    fn bump_dropped(&self) { self.dropped.set(self.dropped.get() + 1) }
+impl Canary {
+    #[inline(always)]
+    fn poison(&self) {
+        // Volatile so the write isn't optimised away as dead.
+        #[cfg(debug_assertions)]
+        unsafe { self.0.as_ptr().write_volatile(DEAD) }
+    }
+
+    #[inline(always)]
+    fn check(&self) {
+        #[cfg(debug_assertions)]
+        {
+            let value = unsafe { self.0.as_ptr().read_volatile() };
+            if value != ALIVE {
+                abort(format_args!(
+                    "A Local waker was used after its Local was dropped (canary: {:#010x}). \
+                     Aborting, as the memory it points to is no longer valid.",
+                    value
+                ));
+            }
+        }
+    }
 }
 
-fn raw_waker(wakey: *const Wakey) -> RawWaker {
-    fn do_clone(data: *const ()) -> RawWaker {
-        unsafe { &*data.cast::<Wakey>() }.bump_cloned();
-        raw_waker(data.cast())
+impl<const LOG: usize> Wakey<LOG> {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        do_clone::<LOG>, do_wake::<LOG>, do_wake_by_ref::<LOG>, do_drop::<LOG>
+    );
+
+    fn stats(&self) -> Stats {
+        Stats {
+            cloned:       self.cloned.get(),
+            dropped:      self.dropped.get(),
+            woken:        self.woken.get(),
+            woken_by_ref: self.woken_by_ref.get(),
+            self_woken:   self.self_woken.get(),
+            polls:        self.polls.get(),
+            pending:      self.pending.get(),
+            spurious:     self.spurious.get(),
+        }
     }
 
-    fn do_wake(data: *const ()) {
-        do_wake_by_ref(data);
-        do_drop(data);
+    fn check_leaks(&self) {
+        let stats = self.stats();
+        if stats.live() == 0 { return; }
+        #[cfg(feature="std")]
+        if std::thread::panicking() { return; }
+        panic!(
+            "Local dropped with {} leaked wakers ({})",
+            stats.live(), stats
+        );
     }
 
-    fn do_wake_by_ref(data: *const ()) {
-        unsafe { &*data.cast::<Wakey>() }.bump_woken()
+    fn bump_cloned(&self)  { self.cloned.set(self.cloned.get().wrapping_add(1)) }
+    fn bump_woken(&self, by_ref: bool) {
+        self.woken.set(self.woken.get().wrapping_add(1));
+        if by_ref { self.woken_by_ref.set(self.woken_by_ref.get().wrapping_add(1)) }
+        if self.polling.get() { self.self_woken.set(self.self_woken.get().wrapping_add(1)) }
     }
+    fn bump_dropped(&self) { self.dropped.set(self.dropped.get().wrapping_add(1)) }
 
-    fn do_drop(data: *const ()) {
-        unsafe { &*data.cast::<Wakey>() }.bump_dropped()
+    fn begin_poll(&self) -> Count {
+        self.owner.claim();
+        let poll = self.polls.get().wrapping_add(1);
+        self.polls.set(poll);
+        if self.seen.replace(self.woken.get()) == self.woken.get() && poll > 1 {
+            self.spurious.set(self.spurious.get().wrapping_add(1));
+        }
+        self.polling.set(true);
+        poll
     }
 
-    RawWaker::new(
-        wakey.cast(),
-        &RawWakerVTable::new(do_clone, do_wake, do_wake_by_ref, do_drop)
-    )
+    fn end_poll(&self, pending: bool) {
+        self.polling.set(false);
+        if pending { self.pending.set(self.pending.get().wrapping_add(1)) }
+    }
+
+    fn record(&self, kind: EventKind) {
+        if LOG == 0 { return; }
+        let poll = if self.polling.get() { Some(self.polls.get()) } else { None };
+        self.log.borrow_mut().push(Event { kind, poll, waker: None });
+    }
+}
+
+fn raw_waker<const LOG: usize>(wakey: *const Wakey<LOG>) -> RawWaker {
+    RawWaker::new(wakey.cast(), &Wakey::<LOG>::VTABLE)
+}
+
+fn wakey<'a, const LOG: usize>(data: *const ()) -> &'a Wakey<LOG> {
+    let wakey = unsafe { &*data.cast::<Wakey<LOG>>() };
+    wakey.canary.check();
+    wakey.owner.check("Local");
+    wakey
+}
+
+fn do_clone<const LOG: usize>(data: *const ()) -> RawWaker {
+    let wakey = wakey::<LOG>(data);
+    wakey.bump_cloned();
+    wakey.record(EventKind::Clone);
+    raw_waker::<LOG>(data.cast())
+}
+
+fn do_wake<const LOG: usize>(data: *const ()) {
+    let wakey = wakey::<LOG>(data);
+    wakey.bump_woken(false);
+    wakey.bump_dropped();
+    wakey.record(EventKind::Wake);
+}
+
+fn do_wake_by_ref<const LOG: usize>(data: *const ()) {
+    let wakey = wakey::<LOG>(data);
+    wakey.bump_woken(true);
+    wakey.record(EventKind::WakeByRef);
+}
+
+fn do_drop<const LOG: usize>(data: *const ()) {
+    let wakey = wakey::<LOG>(data);
+    wakey.bump_dropped();
+    wakey.record(EventKind::Drop);
 }
@@ -0,0 +1,36 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering::{Acquire, Relaxed, Release}};
+
+/// A minimal spin lock, for the rare occasions a waker has to touch
+/// something bigger than an atomic. No-std compatible.
+pub(crate) struct Lock<T> {
+    locked: AtomicBool,
+    value:  UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Lock<T> {}
+
+impl<T: Default> Default for Lock<T> {
+    fn default() -> Self { Lock::new(T::default()) }
+}
+
+impl<T> Lock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Lock { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+
+    /// Runs `f` with exclusive access to the value.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while self.locked.compare_exchange_weak(false, true, Acquire, Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        let _unlock = Unlock(&self.locked);
+        f(unsafe { &mut *self.value.get() })
+    }
+}
+
+struct Unlock<'a>(&'a AtomicBool);
+
+impl<'a> Drop for Unlock<'a> {
+    fn drop(&mut self) { self.0.store(false, Release) }
+}
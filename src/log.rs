@@ -0,0 +1,185 @@
+use crate::Count;
+use core::fmt;
+
+/// Something that was done with a waker.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EventKind {
+    /// The waker was cloned.
+    Clone,
+    /// The waker was woken with `wake`, which also drops it.
+    Wake,
+    /// The waker was woken with `wake_by_ref`.
+    WakeByRef,
+    /// The waker was dropped.
+    Drop,
+}
+
+impl EventKind {
+    /// An event of this kind during the given poll (counting from 1).
+    #[inline(always)]
+    pub fn during(self, poll: Count) -> Event { Event { kind: self, poll: Some(poll), waker: None } }
+
+    /// An event of this kind outside of any poll.
+    #[inline(always)]
+    pub fn outside(self) -> Event { Event { kind: self, poll: None, waker: None } }
+}
+
+/// Identifies one of the wakers handed out by a [`crate::Wookie`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct WakerId {
+    /// Which clone this is, counting from 1 in the order they were
+    /// made. The waker passed to `poll` itself is 0.
+    pub clone:  Count,
+    /// The poll whose waker this one was (ultimately) cloned from.
+    pub poll:   Count,
+    /// The clone this one was cloned from, or `None` for the waker
+    /// passed to `poll` itself.
+    pub parent: Option<Count>,
+}
+
+impl fmt::Display for WakerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "waker #{} from poll {}", self.clone, self.poll)?;
+        match self.parent {
+            Some(parent) => write!(f, " (cloned from #{})", parent),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An entry in a waker event log.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Event {
+    /// What was done with the waker.
+    pub kind:  EventKind,
+    /// The poll during which it happened, counting from 1, or `None`
+    /// if it happened between polls.
+    pub poll:  Option<Count>,
+    /// Which waker it happened to, if known. For a clone, this is the
+    /// new waker. Only recorded by [`crate::Wookie`], for wakers handed
+    /// out while it was logging or checking for leaks.
+    pub waker: Option<WakerId>,
+}
+
+impl Event {
+    /// Sets which waker the event happened to.
+    #[inline(always)]
+    pub fn by(self, waker: WakerId) -> Event { Event { waker: Some(waker), ..self } }
+
+    /// True if `self` matches the `expected` event. The waker is only
+    /// compared if `expected` has one.
+    #[inline(always)]
+    pub fn matches(&self, expected: &Event) -> bool {
+        self.kind == expected.kind && self.poll == expected.poll
+            && (expected.waker.is_none() || self.waker == expected.waker)
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.poll {
+            Some(poll) => write!(f, "{:?} during poll {}", self.kind, poll)?,
+            None => write!(f, "{:?} outside any poll", self.kind)?,
+        }
+        match self.waker {
+            Some(waker) => write!(f, " by {}", waker),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A fixed size waker event log that keeps the most recent `N` events.
+/// Does not require an allocator.
+#[derive(Clone, Copy, Debug)]
+pub struct RingLog<const N: usize> {
+    events:  [Option<Event>; N],
+    next:    usize,
+    pushed:  usize,
+}
+
+impl<const N: usize> Default for RingLog<N> {
+    fn default() -> Self { RingLog { events: [None; N], next: 0, pushed: 0 } }
+}
+
+impl<const N: usize> RingLog<N> {
+    /// Records an event, overwriting the oldest one if full.
+    #[inline(always)]
+    pub fn push(&mut self, event: Event) {
+        if N == 0 { return; }
+        self.events[self.next] = Some(event);
+        self.next = (self.next + 1) % N;
+        self.pushed += 1;
+    }
+
+    /// The number of events held.
+    #[inline(always)]
+    pub fn len(&self) -> usize { self.pushed.min(N) }
+
+    /// True if no events are held.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool { self.pushed == 0 }
+
+    /// The number of events that have been overwritten.
+    #[inline(always)]
+    pub fn overwritten(&self) -> usize { self.pushed - self.len() }
+
+    /// Iterates over the events held, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+        let start = if self.pushed > N { self.next } else { 0 };
+        (0..self.len()).filter_map(move |i| self.events[(start + i) % N])
+    }
+
+    /// Asserts that the events held are exactly `expected`.
+    #[track_caller]
+    pub fn assert(&self, expected: &[Event]) { assert_events(self.iter(), expected) }
+}
+
+/// Asserts that a sequence of waker events matches `expected` (see
+/// [`Event::matches`]), listing both sequences and where they differ
+/// if not.
+///
+/// ## Example
+///
+/// ```
+/// use wookie::{EventKind::*, assert_events};
+/// assert_events([Clone.during(1), Wake.outside()], &[Clone.during(1), Wake.outside()]);
+/// ```
+#[track_caller]
+pub fn assert_events<I: IntoIterator<Item = Event>>(actual: I, expected: &[Event]) {
+    let mut actual = actual.into_iter();
+    let mut seen: usize = 0;
+    loop {
+        match (actual.next(), expected.get(seen)) {
+            (None, None) => return,
+            (Some(a), Some(e)) if a.matches(e) => seen += 1,
+            (a, e) => panic!(
+                "Waker events differ at index {}:\n expected: {}\n   actual: {}\n expected sequence: {}",
+                seen, Show(e.copied()), Show(a), EventList(expected)
+            ),
+        }
+    }
+}
+
+struct Show(Option<Event>);
+
+impl fmt::Display for Show {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(event) => event.fmt(f),
+            None => f.write_str("no more events"),
+        }
+    }
+}
+
+struct EventList<'a>(&'a [Event]);
+
+impl<'a> fmt::Display for EventList<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, event) in self.0.iter().enumerate() {
+            if i > 0 { f.write_str(", ")?; }
+            event.fmt(f)?;
+        }
+        f.write_str("]")
+    }
+}
@@ -0,0 +1,415 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(any(feature="futures-io", feature="tokio"))]
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+/// A mock I/O object that follows a script of reads, writes, errors
+/// and `Pending` returns, for stepping protocol code.
+///
+/// Reads return the scripted data. Writes are checked against the
+/// scripted data and panic if they do not match. A read while the
+/// script expects a write (or vice versa) returns `Pending` and is
+/// woken once the script moves on. Once the script is finished, reads
+/// return EOF and writes panic.
+///
+/// Implements the `futures-io` and `tokio` I/O traits when the
+/// features of the same name are enabled, but can also be used
+/// directly through [`MockIo::poll_read`] and [`MockIo::poll_write`].
+///
+/// ## Panics
+///
+/// When dropped with part of the script unused, unless the thread is
+/// already panicking.
+///
+/// ## Example
+///
+/// ```
+/// use core::future::poll_fn;
+/// use core::task::Poll;
+/// use wookie::{MockIo, wookie};
+/// let mut io = MockIo::builder()
+///     .write(b"ping")
+///     .pending()
+///     .read(b"pong")
+///     .build();
+/// let handle = io.handle();
+/// wookie!(future: async {
+///     let mut buf = [0u8; 4];
+///     poll_fn(|ctx| io.poll_write(ctx, b"ping")).await.unwrap();
+///     poll_fn(|ctx| io.poll_read(ctx, &mut buf)).await.unwrap();
+///     buf
+/// });
+/// assert_eq!(future.poll(), Poll::Pending);
+/// future.stats().assert(1, 0, 0); // the read registered a waker
+/// handle.wake(); // the pong arrives
+/// future.stats().assert(1, 1, 1);
+/// assert_eq!(future.poll(), Poll::Ready(*b"pong"));
+/// ```
+pub struct MockIo {
+    script:      VecDeque<Action>,
+    read_waker:  Option<Waker>,
+    write_waker: Option<Waker>,
+    gate:        Arc<Mutex<Gate>>,
+}
+
+/// Ends the [`MockIoBuilder::pending`] steps of a [`MockIo`] script.
+/// Created by [`MockIo::handle`].
+#[derive(Clone)]
+pub struct MockHandle(Arc<Mutex<Gate>>);
+
+/// How many `Pending` steps have been ended, and the wakers of reads
+/// and writes stuck on the current one.
+#[derive(Default)]
+struct Gate {
+    released: usize,
+    read:     Option<Waker>,
+    write:    Option<Waker>,
+}
+
+/// Builds a script for a [`MockIo`].
+#[derive(Debug, Default)]
+pub struct MockIoBuilder {
+    script: VecDeque<Action>,
+}
+
+#[derive(Debug)]
+enum Action {
+    Read(Vec<u8>),
+    Write(Vec<u8>),
+    Pending,
+    Yield,
+    ReadError(io::Error),
+    WriteError(io::Error),
+    Eof,
+}
+
+impl Action {
+    fn is_read(&self) -> bool {
+        matches!(self, Action::Read(_) | Action::ReadError(_) | Action::Eof)
+    }
+
+    fn is_write(&self) -> bool {
+        matches!(self, Action::Write(_) | Action::WriteError(_))
+    }
+}
+
+impl MockIoBuilder {
+    /// Creates a new, empty script.
+    #[inline(always)]
+    pub fn new() -> Self { MockIoBuilder::default() }
+
+    /// Reads will return `data`, possibly split across several reads.
+    pub fn read(mut self, data: &[u8]) -> Self {
+        self.script.push_back(Action::Read(data.to_vec()));
+        self
+    }
+
+    /// Writes must match `data`, possibly split across several writes.
+    pub fn write(mut self, data: &[u8]) -> Self {
+        self.script.push_back(Action::Write(data.to_vec()));
+        self
+    }
+
+    /// Reads and writes return `Pending`, registering the waker, until
+    /// [`MockHandle::wake`] is called.
+    pub fn pending(mut self) -> Self {
+        self.script.push_back(Action::Pending);
+        self
+    }
+
+    /// The next read or write returns `Pending`, after cloning the
+    /// waker and waking it as if it had been registered and the I/O
+    /// became ready straight away. This counts as the future waking
+    /// itself.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::{MockIo, assert_stats, wookie};
+    /// let mut io = MockIo::builder().yield_now().read(b"x").build();
+    /// wookie!(future: poll_fn(|ctx| io.poll_read(ctx, &mut [0u8; 1])));
+    /// assert!(future.poll().is_pending());
+    /// assert_stats!(future.stats(), woken: 1, self_woken: 1);
+    /// assert!(future.poll().is_ready());
+    /// ```
+    pub fn yield_now(mut self) -> Self {
+        self.script.push_back(Action::Yield);
+        self
+    }
+
+    /// The next read fails with `error`.
+    pub fn read_error(mut self, error: io::Error) -> Self {
+        self.script.push_back(Action::ReadError(error));
+        self
+    }
+
+    /// The next write fails with `error`.
+    pub fn write_error(mut self, error: io::Error) -> Self {
+        self.script.push_back(Action::WriteError(error));
+        self
+    }
+
+    /// The next read returns EOF even though the script continues.
+    pub fn eof(mut self) -> Self {
+        self.script.push_back(Action::Eof);
+        self
+    }
+
+    /// Creates the [`MockIo`].
+    pub fn build(self) -> MockIo {
+        MockIo { script: self.script, read_waker: None, write_waker: None, gate: Arc::default() }
+    }
+}
+
+impl MockIo {
+    /// Creates a [`MockIoBuilder`] to script a [`MockIo`].
+    #[inline(always)]
+    pub fn builder() -> MockIoBuilder { MockIoBuilder::new() }
+
+    /// Returns a handle for ending the [`MockIoBuilder::pending`] steps
+    /// of the script from outside the future using us.
+    #[inline(always)]
+    pub fn handle(&self) -> MockHandle { MockHandle(self.gate.clone()) }
+
+    /// True if the whole script has been used.
+    #[inline(always)]
+    pub fn is_done(&self) -> bool { self.script.is_empty() }
+
+    /// Attempts to read into `buf` according to the script.
+    pub fn poll_read(&mut self, ctx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match self.script.front_mut() {
+            None => Poll::Ready(Ok(0)),
+            Some(Action::Read(data)) => {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                data.drain(..len);
+                if data.is_empty() { self.next(); }
+                Poll::Ready(Ok(len))
+            }
+            Some(Action::Eof) => {
+                self.next();
+                Poll::Ready(Ok(0))
+            }
+            Some(Action::ReadError(_)) => match self.next() {
+                Some(Action::ReadError(e)) => Poll::Ready(Err(e)),
+                _ => unreachable!(),
+            },
+            Some(Action::Pending) => {
+                if !self.release(ctx, true) { return Poll::Pending; }
+                self.poll_read(ctx, buf)
+            }
+            Some(Action::Yield) => self.yield_now(ctx),
+            Some(_) => {
+                register(&mut self.read_waker, ctx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Attempts to write `data` according to the script.
+    ///
+    /// ## Panics
+    ///
+    /// If `data` does not match the script, or the script is finished.
+    pub fn poll_write(&mut self, ctx: &mut Context, data: &[u8]) -> Poll<io::Result<usize>> {
+        match self.script.front_mut() {
+            None => panic!("MockIo: unexpected write of {} after the script finished!", Bytes(data)),
+            Some(Action::Write(expected)) => {
+                let len = expected.len().min(data.len());
+                if data[..len] != expected[..len] {
+                    panic!("MockIo: write did not match the script!\n expected: {}\n      got: {}",
+                           Bytes(expected), Bytes(data));
+                }
+                expected.drain(..len);
+                if expected.is_empty() { self.next(); }
+                Poll::Ready(Ok(len))
+            }
+            Some(Action::WriteError(_)) => match self.next() {
+                Some(Action::WriteError(e)) => Poll::Ready(Err(e)),
+                _ => unreachable!(),
+            },
+            Some(Action::Pending) => {
+                if !self.release(ctx, false) { return Poll::Pending; }
+                self.poll_write(ctx, data)
+            }
+            Some(Action::Yield) => self.yield_now(ctx),
+            Some(_) => {
+                register(&mut self.write_waker, ctx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Moves past a `Pending` step if the handle has ended it, or
+    /// registers the waker of a read or write with the handle if not.
+    fn release(&mut self, ctx: &mut Context, read: bool) -> bool {
+        let mut gate = lock(&self.gate);
+        if gate.released == 0 {
+            register(if read { &mut gate.read } else { &mut gate.write }, ctx.waker());
+            return false;
+        }
+        gate.released -= 1;
+        let wakers = (gate.read.take(), gate.write.take());
+        drop(gate);
+        // The other side may be stuck on this step too.
+        wake_all(wakers);
+        self.next();
+        true
+    }
+
+    #[allow(clippy::waker_clone_wake)]
+    fn yield_now(&mut self, ctx: &mut Context) -> Poll<io::Result<usize>> {
+        self.next();
+        // Clone rather than wake_by_ref, as a real registration would.
+        ctx.waker().clone().wake();
+        Poll::Pending
+    }
+
+    /// Moves on to the next step of the script, waking whichever side
+    /// it is for. Either side may end up on a `Pending` or `Yield`
+    /// step, so both are woken for those.
+    fn next(&mut self) -> Option<Action> {
+        let action = self.script.pop_front();
+        let (read, write) = match self.script.front() {
+            None | Some(Action::Pending) | Some(Action::Yield) => (true, true),
+            Some(next) => (next.is_read(), next.is_write()),
+        };
+        if read { if let Some(waker) = self.read_waker.take() { waker.wake() } }
+        if write { if let Some(waker) = self.write_waker.take() { waker.wake() } }
+        action
+    }
+}
+
+impl MockHandle {
+    /// Ends the `Pending` step the script is on, waking the reads and
+    /// writes stuck on it. If the script has not reached one yet, the
+    /// next one it reaches is skipped.
+    ///
+    /// ## Example
+    ///
+    /// Reading and writing at the same time across a `Pending` step:
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::{MockIo, wookie};
+    /// let mut io = MockIo::builder().write(b"a").pending().read(b"b").build();
+    /// let handle = io.handle();
+    /// let mut buf = [0u8; 1];
+    /// let (mut read, mut written) = (false, false);
+    /// wookie!(future: poll_fn(|ctx| {
+    ///     if !read { read = io.poll_read(ctx, &mut buf).is_ready(); }
+    ///     if !written { written = io.poll_write(ctx, b"a").is_ready(); }
+    ///     if read && written { Poll::Ready(buf) } else { Poll::Pending }
+    /// }));
+    /// // The write moves the script on, waking the read to reach `Pending`.
+    /// assert_eq!(future.poll_while_woken(), Poll::Pending);
+    /// let woken = future.woken();
+    /// handle.wake();
+    /// assert_eq!(future.woken(), woken + 1);
+    /// assert_eq!(future.poll(), Poll::Ready(*b"b"));
+    /// ```
+    pub fn wake(&self) {
+        let wakers = {
+            let mut gate = lock(&self.0);
+            gate.released += 1;
+            (gate.read.take(), gate.write.take())
+        };
+        wake_all(wakers)
+    }
+}
+
+impl Drop for MockIo {
+    fn drop(&mut self) {
+        if !std::thread::panicking() && !self.script.is_empty() {
+            panic!("MockIo dropped with unused script: {:?}", self.script);
+        }
+    }
+}
+
+/// Stores a clone of `waker` in `slot`, unless it would wake the same
+/// task as the one already there.
+pub(crate) fn register(slot: &mut Option<Waker>, waker: &Waker) {
+    match slot {
+        Some(w) if w.will_wake(waker) => {}
+        _ => *slot = Some(waker.clone()),
+    }
+}
+
+fn wake_all((read, write): (Option<Waker>, Option<Waker>)) {
+    if let Some(waker) = read { waker.wake() }
+    if let Some(waker) = write { waker.wake() }
+}
+
+fn lock(gate: &Mutex<Gate>) -> MutexGuard<'_, Gate> {
+    gate.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Formats bytes like a byte string literal.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for Bytes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("b\"")?;
+        for byte in self.0 {
+            write!(f, "{}", core::ascii::escape_default(*byte))?;
+        }
+        f.write_str("\"")
+    }
+}
+
+#[cfg(feature="futures-io")]
+impl futures_io::AsyncRead for MockIo {
+    fn poll_read(self: Pin<&mut Self>, ctx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        MockIo::poll_read(self.get_mut(), ctx, buf)
+    }
+}
+
+#[cfg(feature="futures-io")]
+impl futures_io::AsyncWrite for MockIo {
+    fn poll_write(self: Pin<&mut Self>, ctx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        MockIo::poll_write(self.get_mut(), ctx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature="tokio")]
+impl tokio::io::AsyncRead for MockIo {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        MockIo::poll_read(self.get_mut(), ctx, buf.initialize_unfilled())
+            .map_ok(|len| buf.advance(len))
+    }
+}
+
+#[cfg(feature="tokio")]
+impl tokio::io::AsyncWrite for MockIo {
+    fn poll_write(self: Pin<&mut Self>, ctx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        MockIo::poll_write(self.get_mut(), ctx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
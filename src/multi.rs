@@ -0,0 +1,366 @@
+use crate::*;
+use crate::explore::Script;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::Poll;
+use core::time::Duration;
+
+type Boxed<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// A handle to a task spawned on a [`Multi`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Task(pub(crate) usize);
+
+impl Task {
+    /// The position of the task in spawn order, starting at 0.
+    #[inline(always)]
+    pub fn index(self) -> usize { self.0 }
+}
+
+/// A multi-future stepping executor for test suites that tracks
+/// wakers separately for each task.
+///
+/// Each task is driven by its own [`Wookie`], so you get the same
+/// [`Stats`] per task as you would for a single future.
+///
+/// A task counts as woken if it has never been polled or if its waker
+/// has been woken since the start of its last poll.
+///
+/// ## Examples
+///
+/// ```
+/// use core::task::Poll;
+/// use wookie::Multi;
+/// let mut tasks = Multi::new();
+/// let a = tasks.spawn(async { 1 });
+/// let b = tasks.spawn(async { 2 });
+///
+/// // you can poll a specific task...
+/// assert_eq!(tasks.poll(b), Poll::Ready(2));
+/// // or whichever one is next woken...
+/// assert_eq!(tasks.poll_next_woken(), Some((a, Poll::Ready(1))));
+/// // until there are none left.
+/// assert_eq!(tasks.poll_next_woken(), None);
+///
+/// // we can find out about the state of each task's wakers any time:
+/// tasks.stats(a).assert(0, 0, 0);
+/// ```
+pub struct Multi<'a, T> {
+    tasks: Vec<Slot<'a, T>>,
+    last: Option<usize>,
+    schedule: Schedule,
+}
+
+enum Schedule {
+    RoundRobin,
+    Random(Rng),
+    Script(Script),
+}
+
+struct Slot<'a, T> {
+    wookie: Wookie<Boxed<'a, T>>,
+    seen: Option<Count>,
+    done: bool,
+}
+
+impl<'a, T> Slot<'a, T> {
+    #[inline(always)]
+    fn wookie(&mut self) -> Pin<&mut Wookie<Boxed<'a, T>>> { Pin::new(&mut self.wookie) }
+
+    fn is_woken(&mut self) -> bool {
+        if self.done { return false; }
+        match self.seen {
+            None => true,
+            Some(seen) => self.wookie().woken() != seen,
+        }
+    }
+}
+
+impl<'a, T> Default for Multi<'a, T> {
+    fn default() -> Self { Multi::new() }
+}
+
+impl<'a, T> Multi<'a, T> {
+    /// Creates a new [`Multi`] with no tasks.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Multi { tasks: Vec::new(), last: None, schedule: Schedule::RoundRobin }
+    }
+
+    /// Creates a new [`Multi`] with no tasks that picks the next woken
+    /// task at random. The same seed always produces the same choices,
+    /// so a failing interleaving can be replayed by reusing its seed.
+    ///
+    /// With the `std` feature, the seed is printed to stderr if the
+    /// [`Multi`] is dropped during a panic.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use wookie::Multi;
+    /// let mut tasks = Multi::with_seed(42);
+    /// let a = tasks.spawn(async { 1 });
+    /// let b = tasks.spawn(async { 2 });
+    /// assert_eq!(tasks.seed(), Some(42));
+    /// let mut done = tasks.poll_while_woken();
+    /// done.sort();
+    /// assert_eq!(done, vec![(a, 1), (b, 2)]);
+    /// ```
+    #[inline(always)]
+    pub fn with_seed(seed: u64) -> Self {
+        Multi { tasks: Vec::new(), last: None, schedule: Schedule::Random(Rng::new(seed)) }
+    }
+
+    /// Like [`Multi::with_seed`], but takes the seed from the
+    /// `WOOKIE_SEED` environment variable, or the system clock if it
+    /// is not set.
+    ///
+    /// ## Panics
+    ///
+    /// If `WOOKIE_SEED` is set but is not a valid `u64`.
+    #[cfg(feature="std")]
+    pub fn with_env_seed() -> Self {
+        let seed = match std::env::var("WOOKIE_SEED") {
+            Ok(var) => var.parse().expect("WOOKIE_SEED must be a u64"),
+            Err(_) => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0),
+        };
+        Multi::with_seed(seed)
+    }
+
+    /// The seed random scheduling was started with, if any.
+    #[inline(always)]
+    pub fn seed(&self) -> Option<u64> {
+        if let Schedule::Random(rng) = &self.schedule { Some(rng.seed) } else { None }
+    }
+
+    /// Creates a new [`Multi`] with no tasks that picks woken tasks in
+    /// the order given by a [`Trace`], such as one reported by
+    /// [`Explore`]. Once the trace runs out, tasks are picked
+    /// round-robin.
+    ///
+    /// ## Panics
+    ///
+    /// When polling the next woken task, if the task the trace names
+    /// is not woken. This means the futures did not behave the same
+    /// way as when the trace was recorded.
+    #[inline(always)]
+    pub fn with_trace(trace: Trace) -> Self {
+        Multi { tasks: Vec::new(), last: None, schedule: Schedule::Script(Script::replay(trace)) }
+    }
+
+    pub(crate) fn with_script(script: Script) -> Self {
+        Multi { tasks: Vec::new(), last: None, schedule: Schedule::Script(script) }
+    }
+
+    pub(crate) fn into_script(mut self) -> Script {
+        match core::mem::replace(&mut self.schedule, Schedule::RoundRobin) {
+            Schedule::Script(script) => script,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a future as a new task, returning its handle. New tasks
+    /// count as woken until they are first polled.
+    pub fn spawn<F>(&mut self, future: F) -> Task
+    where F: Future<Output = T> + 'a {
+        let future: Boxed<'a, T> = Box::pin(future);
+        let wookie = Wookie::new(future);
+        self.tasks.push(Slot { wookie, seen: None, done: false });
+        Task(self.tasks.len() - 1)
+    }
+
+    /// The number of tasks that have been spawned, including any
+    /// that have completed.
+    #[inline(always)]
+    pub fn len(&self) -> usize { self.tasks.len() }
+
+    /// True if no tasks have been spawned.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool { self.tasks.is_empty() }
+
+    /// True if the task has returned [`Poll::Ready`].
+    #[inline(always)]
+    pub fn is_done(&self, task: Task) -> bool { self.tasks[task.0].done }
+
+    /// True if every task has returned [`Poll::Ready`].
+    pub fn all_done(&self) -> bool { self.tasks.iter().all(|t| t.done) }
+
+    /// True if the task has not completed and has either never been
+    /// polled or been woken since the start of its last poll.
+    #[inline(always)]
+    pub fn is_woken(&mut self, task: Task) -> bool { self.tasks[task.0].is_woken() }
+
+    /// Returns the handles of all currently woken tasks in spawn order.
+    pub fn woken(&mut self) -> Vec<Task> {
+        let mut ret = Vec::new();
+        for (i, slot) in self.tasks.iter_mut().enumerate() {
+            if slot.is_woken() { ret.push(Task(i)); }
+        }
+        ret
+    }
+
+    /// Returns statistics about use of the task's wakers.
+    #[inline(always)]
+    pub fn stats(&mut self, task: Task) -> Stats {
+        self.tasks[task.0].wookie().stats()
+    }
+
+    /// Polls the given task once, whether or not it has been woken.
+    ///
+    /// ## Panics
+    ///
+    /// If the task has already completed.
+    pub fn poll(&mut self, task: Task) -> Poll<T> {
+        let slot = &mut self.tasks[task.0];
+        assert!(!slot.done, "Task {} polled after it completed!", task.0);
+        let mut wookie = Pin::new(&mut slot.wookie);
+        slot.seen = Some(wookie.woken());
+        let ret = wookie.poll();
+        if ret.is_ready() { slot.done = true; }
+        self.last = Some(task.0);
+        ret
+    }
+
+    /// Polls the next woken task once, returning its handle along with
+    /// the result, or `None` if no tasks are woken.
+    ///
+    /// Tasks are picked round-robin in spawn order, starting after the
+    /// task that was last polled, unless a seed was provided with
+    /// [`Multi::with_seed`], in which case they are picked at random,
+    /// or a trace was provided with [`Multi::with_trace`].
+    pub fn poll_next_woken(&mut self) -> Option<(Task, Poll<T>)> {
+        let task = self.next_woken()?;
+        Some((task, self.poll(task)))
+    }
+
+    /// Polls woken tasks until none are woken, returning the outputs
+    /// of the tasks that completed in the order they completed.
+    ///
+    /// ## Example
+    ///
+    /// A consumer waiting on a value that a producer sends:
+    ///
+    /// ```
+    /// use core::cell::RefCell;
+    /// use core::future::poll_fn;
+    /// use core::task::{Poll, Waker};
+    /// use wookie::Multi;
+    ///
+    /// let slot: RefCell<(Option<i32>, Option<Waker>)> = RefCell::new((None, None));
+    /// let mut tasks = Multi::new();
+    /// let consumer = tasks.spawn(poll_fn(|ctx| {
+    ///     let mut slot = slot.borrow_mut();
+    ///     match slot.0.take() {
+    ///         Some(value) => Poll::Ready(value),
+    ///         None => { slot.1 = Some(ctx.waker().clone()); Poll::Pending }
+    ///     }
+    /// }));
+    /// assert_eq!(tasks.poll(consumer), Poll::Pending);
+    /// let producer = tasks.spawn(async {
+    ///     let mut slot = slot.borrow_mut();
+    ///     slot.0 = Some(42);
+    ///     if let Some(waker) = slot.1.take() { waker.wake() }
+    ///     0
+    /// });
+    /// assert_eq!(tasks.poll_while_woken(), vec![(producer, 0), (consumer, 42)]);
+    /// tasks.stats(consumer).assert(1, 1, 1);
+    /// tasks.stats(producer).assert(0, 0, 0);
+    /// ```
+    pub fn poll_while_woken(&mut self) -> Vec<(Task, T)> {
+        let mut ret = Vec::new();
+        while let Some((task, poll)) = self.poll_next_woken() {
+            if let Poll::Ready(r) = poll { ret.push((task, r)); }
+        }
+        ret
+    }
+
+    /// Like [`Multi::poll_while_woken`], but whenever no tasks are
+    /// woken, moves the [`Clock`] forward to the next timer deadline,
+    /// so timeouts fire instantly. Stops when there are no timers left.
+    /// Returns the virtual time that passed along with the outputs.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::time::Duration;
+    /// use wookie::{Clock, Multi};
+    /// let clock = Clock::new();
+    /// let mut tasks = Multi::new();
+    /// let slow = tasks.spawn(async { clock.sleep(Duration::from_secs(10)).await; 10 });
+    /// let fast = tasks.spawn(async { clock.sleep(Duration::from_secs(1)).await; 1 });
+    /// let (done, elapsed) = tasks.run_until_stalled_with_time(&clock);
+    /// assert_eq!(done, vec![(fast, 1), (slow, 10)]);
+    /// assert_eq!(elapsed, Duration::from_secs(10));
+    /// ```
+    pub fn run_until_stalled_with_time(&mut self, clock: &Clock) -> (Vec<(Task, T)>, Duration) {
+        let start = clock.now();
+        let mut ret = Vec::new();
+        loop {
+            ret.extend(self.poll_while_woken());
+            match clock.next_deadline() {
+                Some(deadline) => { clock.advance_to(deadline); }
+                None => return (ret, clock.now() - start),
+            }
+        }
+    }
+
+    fn next_woken(&mut self) -> Option<Task> {
+        let woken = self.round_robin();
+        if woken.is_empty() { return None; }
+        let task = match &mut self.schedule {
+            Schedule::RoundRobin => woken[0],
+            Schedule::Random(rng) => woken[rng.below(woken.len())],
+            Schedule::Script(script) => script.choose(self.last, &woken),
+        };
+        Some(Task(task))
+    }
+
+    /// The indices of woken tasks in round-robin order.
+    fn round_robin(&mut self) -> Vec<usize> {
+        let len = self.tasks.len();
+        let start = self.last.map(|l| l + 1).unwrap_or(0);
+        let mut ret = Vec::new();
+        for i in (0..len).map(|i| (start + i) % len) {
+            if self.tasks[i].is_woken() { ret.push(i); }
+        }
+        ret
+    }
+}
+
+#[cfg(feature="std")]
+impl<'a, T> Drop for Multi<'a, T> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() { return; }
+        if let Some(seed) = self.seed() {
+            std::eprintln!("wookie: Multi was scheduling with seed {}. Replay with Multi::with_seed({}).", seed, seed);
+        }
+        if let Schedule::Script(script) = &self.schedule { script.report_panic() }
+    }
+}
+
+/// A small, fast, seedable PRNG (splitmix64). Not remotely suitable
+/// for anything but picking tasks.
+struct Rng {
+    seed:  u64,
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self { Rng { seed, state: seed } }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..max`. Slightly biased, which does not matter here.
+    fn below(&mut self, max: usize) -> usize { (self.next() % max as u64) as usize }
+}
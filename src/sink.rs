@@ -0,0 +1,44 @@
+use crate::Stats;
+
+/// One of the methods of a `Sink`, for looking up the waker and poll
+/// activity it caused with `sink_stats`.
+///
+/// Activity between calls, such as a wake from the other end of a
+/// channel, is put down to the last method called.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SinkPhase {
+    /// `poll_ready`.
+    Ready,
+    /// `start_send`.
+    Send,
+    /// `poll_flush`.
+    Flush,
+    /// `poll_close`.
+    Close,
+}
+
+/// Tallies activity by the [`SinkPhase`] it happened in.
+#[derive(Default)]
+pub(crate) struct SinkStats {
+    phase:  Option<SinkPhase>,
+    /// The stats as of entering `phase`.
+    mark:   Stats,
+    phases: [Stats; 4],
+}
+
+impl SinkStats {
+    /// Enters a phase, given the stats as of now.
+    pub(crate) fn enter(&mut self, phase: SinkPhase, now: Stats) {
+        if let Some(last) = self.phase {
+            self.phases[last as usize] = self.phases[last as usize] + (now - self.mark);
+        }
+        self.phase = Some(phase);
+        self.mark = now;
+    }
+
+    /// The activity in a phase, given the stats as of now.
+    pub(crate) fn get(&self, phase: SinkPhase, now: Stats) -> Stats {
+        let stats = self.phases[phase as usize];
+        if self.phase == Some(phase) { stats + (now - self.mark) } else { stats }
+    }
+}
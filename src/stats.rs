@@ -0,0 +1,325 @@
+use core::fmt;
+use core::ops::{Add, Sub};
+
+/// The integer type of the counters in [`Stats`] and of poll numbers.
+///
+/// `u16` by default, `u32` with the `counter-u32` feature and `u64` with
+/// the `counter-u64` feature. Counters wrap around on overflow, and so
+/// do the poll numbers in [`crate::WakerId`], [`crate::Event`] and
+/// [`crate::LostWakeup`].
+pub type Count = count::Count;
+
+mod count {
+    #[cfg(not(any(feature="counter-u32", feature="counter-u64")))]
+    pub type Count = u16;
+    #[cfg(all(feature="counter-u32", not(feature="counter-u64")))]
+    pub type Count = u32;
+    #[cfg(feature="counter-u64")]
+    pub type Count = u64;
+}
+
+/// Statistics of waker and poll activity for [`crate::Wookie`],
+/// [`crate::Local`] or [`crate::Dummy`].
+///
+/// A `Stats` is a snapshot: it does not change as the wakers do.
+/// Subtracting an earlier snapshot from a later one gives the activity
+/// in between.
+///
+/// ## Example
+///
+/// ```
+/// use core::future::poll_fn;
+/// use core::task::Poll;
+/// use wookie::{assert_stats, wookie};
+/// let mut stash = None;
+/// wookie!(future: poll_fn(|ctx| {
+///     stash = Some(ctx.waker().clone());
+///     Poll::<()>::Pending
+/// }));
+/// let before = future.stats();
+/// future.poll();
+/// let delta = future.stats() - before;
+/// assert_stats!(delta, cloned: 1, woken: 0, polls: 1, pending: 1; "first poll");
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Stats {
+    /// The number of times a Waker has been cloned. Usually equivalent to the
+    /// number of times a waker has been set.
+    pub cloned:       Count,
+    /// The number of times a Waker has been dropped. Note that `wake` causes
+    /// this count to be incremented as it takes ownership of the Waker.
+    pub dropped:      Count,
+    /// The number of times a Waker has been woken. Includes calls to both
+    /// `wake` and `wake_by_ref`.
+    pub woken:        Count,
+    /// The number of those wakes that were by `wake_by_ref`.
+    pub woken_by_ref: Count,
+    /// The number of those wakes that happened during a poll, i.e. the
+    /// future woke itself, such as when yielding.
+    pub self_woken:   Count,
+    /// The number of times the future has been polled.
+    pub polls:        Count,
+    /// The number of polls that returned `Pending`.
+    pub pending:      Count,
+    /// The number of polls that were not preceded by a wake since the
+    /// previous poll began. The first poll is never spurious.
+    pub spurious:     Count,
+}
+
+impl Stats {
+    /// The number of live wakers, i.e. `cloned - dropped`. Correct even
+    /// if the counters have wrapped around.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use wookie::{Count, Stats};
+    /// // `cloned` has wrapped around, but `dropped` hasn't yet.
+    /// let stats = Stats { cloned: 1, dropped: Count::MAX, ..Stats::default() };
+    /// assert_eq!(stats.live(), 2);
+    /// ```
+    #[inline(always)]
+    pub fn live(&self) -> Count { self.cloned.wrapping_sub(self.dropped) }
+
+    /// The number of wakes by `wake`, i.e. `woken - woken_by_ref`.
+    #[inline(always)]
+    pub fn woken_by_value(&self) -> Count { self.woken.wrapping_sub(self.woken_by_ref) }
+
+    /// The number of wakes from outside a poll, i.e. `woken - self_woken`.
+    #[inline(always)]
+    pub fn externally_woken(&self) -> Count { self.woken.wrapping_sub(self.self_woken) }
+
+    /// Assert that `cloned`, `dropped` and `woken` are the provided values.
+    #[track_caller]
+    pub fn assert(&self, cloned: Count, dropped: Count, woken: Count) {
+        self.__assert_expected(&ExpectedStats::all(cloned, dropped, woken), None)
+    }
+
+    /// Like [`Stats::assert`], but adds `context` to the failure message.
+    ///
+    /// ## Example
+    ///
+    /// ```should_panic
+    /// use wookie::Stats;
+    /// let stats = Stats { cloned: 1, ..Stats::default() };
+    /// // Waker stats differ from expected (after the first poll): ...
+    /// stats.assert_with(1, 1, 0, "after the first poll");
+    /// ```
+    #[track_caller]
+    pub fn assert_with(&self, cloned: Count, dropped: Count, woken: Count, context: impl fmt::Display) {
+        self.__assert_expected(&ExpectedStats::all(cloned, dropped, woken), Some(format_args!("{}", context)))
+    }
+
+    #[doc(hidden)]
+    #[track_caller]
+    pub fn __assert_expected(&self, expected: &ExpectedStats, context: Option<fmt::Arguments>) {
+        let fields = [
+            ("cloned",           expected.cloned,           self.cloned),
+            ("dropped",          expected.dropped,          self.dropped),
+            ("woken",            expected.woken,            self.woken),
+            ("woken_by_value",   expected.woken_by_value,   self.woken_by_value()),
+            ("woken_by_ref",     expected.woken_by_ref,     self.woken_by_ref),
+            ("self_woken",       expected.self_woken,       self.self_woken),
+            ("externally_woken", expected.externally_woken, self.externally_woken()),
+            ("live",             expected.live,             self.live()),
+            ("polls",            expected.polls,            self.polls),
+            ("pending",          expected.pending,          self.pending),
+            ("spurious",         expected.spurious,         self.spurious),
+        ];
+        if fields.iter().any(|(_, e, a)| matches!(e, Some(e) if e != a)) {
+            panic!("Waker stats differ from expected{}:\n{}", Context(context), Diff(fields));
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cloned: {}, dropped: {}, woken: {}, woken_by_ref: {}, self_woken: {}, \
+             polls: {}, pending: {}, spurious: {}",
+            self.cloned, self.dropped, self.woken, self.woken_by_ref, self.self_woken,
+            self.polls, self.pending, self.spurious
+        )
+    }
+}
+
+/// The activity between two snapshots, `self` being the later one.
+impl Sub for Stats {
+    type Output = Stats;
+    fn sub(self, earlier: Stats) -> Stats {
+        Stats {
+            cloned:       self.cloned.wrapping_sub(earlier.cloned),
+            dropped:      self.dropped.wrapping_sub(earlier.dropped),
+            woken:        self.woken.wrapping_sub(earlier.woken),
+            woken_by_ref: self.woken_by_ref.wrapping_sub(earlier.woken_by_ref),
+            self_woken:   self.self_woken.wrapping_sub(earlier.self_woken),
+            polls:        self.polls.wrapping_sub(earlier.polls),
+            pending:      self.pending.wrapping_sub(earlier.pending),
+            spurious:     self.spurious.wrapping_sub(earlier.spurious),
+        }
+    }
+}
+
+/// The combined activity of two deltas.
+impl Add for Stats {
+    type Output = Stats;
+    fn add(self, other: Stats) -> Stats {
+        Stats {
+            cloned:       self.cloned.wrapping_add(other.cloned),
+            dropped:      self.dropped.wrapping_add(other.dropped),
+            woken:        self.woken.wrapping_add(other.woken),
+            woken_by_ref: self.woken_by_ref.wrapping_add(other.woken_by_ref),
+            self_woken:   self.self_woken.wrapping_add(other.self_woken),
+            polls:        self.polls.wrapping_add(other.polls),
+            pending:      self.pending.wrapping_add(other.pending),
+            spurious:     self.spurious.wrapping_add(other.spurious),
+        }
+    }
+}
+
+/// Which wakes cause `poll_while_woken_by` to poll again.
+///
+/// ## Example
+///
+/// ```
+/// use core::future::poll_fn;
+/// use core::task::Poll;
+/// use wookie::{WakePolicy, wookie};
+/// // A future that keeps yielding.
+/// wookie!(future: poll_fn(|ctx| {
+///     ctx.waker().wake_by_ref();
+///     Poll::<()>::Pending
+/// }));
+/// // it only woke itself, so we stop after one poll.
+/// assert!(future.poll_while_woken_by(WakePolicy::External).is_pending());
+/// assert_eq!(future.polls(), 1);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum WakePolicy {
+    /// Any wake. What `poll_while_woken` does.
+    Any,
+    /// Only wakes during a poll, i.e. the future waking itself.
+    SelfWake,
+    /// Only wakes from outside a poll. Stops a future that keeps
+    /// yielding from being polled over and over.
+    External,
+}
+
+impl WakePolicy {
+    /// The number of wakes in `stats` that count under this policy.
+    #[inline(always)]
+    pub fn count(self, stats: &Stats) -> Count {
+        match self {
+            WakePolicy::Any => stats.woken,
+            WakePolicy::SelfWake => stats.self_woken,
+            WakePolicy::External => stats.externally_woken(),
+        }
+    }
+}
+
+/// The values [`assert_stats!`] checks for. `None` is not checked.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct ExpectedStats {
+    pub cloned:           Option<Count>,
+    pub dropped:          Option<Count>,
+    pub woken:            Option<Count>,
+    pub woken_by_value:   Option<Count>,
+    pub woken_by_ref:     Option<Count>,
+    pub self_woken:       Option<Count>,
+    pub externally_woken: Option<Count>,
+    pub live:             Option<Count>,
+    pub polls:            Option<Count>,
+    pub pending:          Option<Count>,
+    pub spurious:         Option<Count>,
+}
+
+impl ExpectedStats {
+    pub const NONE: ExpectedStats = ExpectedStats {
+        cloned: None, dropped: None, woken: None, woken_by_value: None, woken_by_ref: None,
+        self_woken: None, externally_woken: None, live: None, polls: None, pending: None, spurious: None,
+    };
+
+    fn all(cloned: Count, dropped: Count, woken: Count) -> Self {
+        ExpectedStats { cloned: Some(cloned), dropped: Some(dropped), woken: Some(woken), ..ExpectedStats::NONE }
+    }
+}
+
+struct Context<'a>(Option<fmt::Arguments<'a>>);
+
+impl<'a> fmt::Display for Context<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(context) => write!(f, " ({})", context),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Diff([(&'static str, Option<Count>, Count); 11]);
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  {:<16} {:>10} {:>10}", "", "expected", "actual")?;
+        for (name, expected, actual) in self.0.iter() {
+            match expected {
+                Some(e) if e != actual => writeln!(f, "  {:<16} {:>10} {:>10}  <-- differs", name, e, actual)?,
+                Some(e) => writeln!(f, "  {:<16} {:>10} {:>10}", name, e, actual)?,
+                None => writeln!(f, "  {:<16} {:>10} {:>10}", name, "-", actual)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[macro_export]
+/// Asserts that some of the fields of a [`Stats`] have the given
+/// values, printing a table of differences if not. Any of the fields
+/// of [`Stats`], as well as `live`, `woken_by_value` and
+/// `externally_woken`, may be given. A format string and
+/// arguments for context may follow a semicolon.
+///
+/// ## Examples
+///
+/// ```
+/// use wookie::{Stats, assert_stats};
+/// let stats = Stats { cloned: 2, dropped: 1, woken: 1, woken_by_ref: 1, ..Stats::default() };
+/// assert_stats!(stats, live: 1);
+/// assert_stats!(stats, cloned: 2, woken: 1; "after {} polls", 3);
+/// ```
+///
+/// ```should_panic
+/// use wookie::{Stats, assert_stats};
+/// let stats = Stats { cloned: 2, dropped: 1, woken: 1, woken_by_ref: 1, ..Stats::default() };
+/// // Waker stats differ from expected:
+/// //                     expected     actual
+/// //   cloned                    -          2
+/// //   dropped                   -          1
+/// //   woken                     0          1  <-- differs
+/// //   woken_by_value            -          0
+/// //   woken_by_ref              -          1
+/// //   self_woken                -          0
+/// //   externally_woken          -          1
+/// //   live                      -          1
+/// //   polls                     -          0
+/// //   pending                   -          0
+/// //   spurious                  -          0
+/// assert_stats!(stats, woken: 0);
+/// ```
+macro_rules! assert_stats {
+    ($stats:expr, $($field:ident: $value:expr),+ $(,)?) => {
+        $crate::Stats::__assert_expected(
+            &$stats,
+            &$crate::ExpectedStats { $($field: ::core::option::Option::Some($value),)+ ..$crate::ExpectedStats::NONE },
+            ::core::option::Option::None,
+        )
+    };
+    ($stats:expr, $($field:ident: $value:expr),+ ; $($context:tt)+) => {
+        $crate::Stats::__assert_expected(
+            &$stats,
+            &$crate::ExpectedStats { $($field: ::core::option::Option::Some($value),)+ ..$crate::ExpectedStats::NONE },
+            ::core::option::Option::Some(format_args!($($context)+)),
+        )
+    };
+}
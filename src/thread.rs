@@ -0,0 +1,70 @@
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering::Relaxed};
+
+static HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets a function that identifies the current thread, so wakers can
+/// check they are only used on the thread that polled them.
+///
+/// With the `std` feature, we tell threads apart ourselves and the
+/// hook is ignored, so you only need this without it. `hook` must
+/// return a different non-zero value on each thread that is alive at
+/// the same time.
+///
+/// There is one hook for the whole process, so setting it again
+/// replaces it for every executor, not just the ones you create later.
+///
+/// ## Example
+///
+/// ```
+/// // A single threaded platform.
+/// wookie::set_thread_id_hook(|| 1);
+/// ```
+pub fn set_thread_id_hook(hook: fn() -> usize) {
+    HOOK.store(hook as *mut (), Relaxed)
+}
+
+/// Identifies the current thread, if we know how.
+#[cfg(feature="std")]
+fn current_thread() -> Option<usize> {
+    std::thread_local!(static MARK: u8 = const { 0 });
+    // Fails while thread locals are being destroyed, in which case we
+    // give up on checking.
+    MARK.try_with(|mark| mark as *const u8 as usize).ok()
+}
+
+/// Identifies the current thread, if we know how.
+#[cfg(not(feature="std"))]
+fn current_thread() -> Option<usize> {
+    let hook = HOOK.load(Relaxed);
+    if hook.is_null() { return None; }
+    // Safety: we only ever store a `fn() -> usize`.
+    let hook = unsafe { core::mem::transmute::<*mut (), fn() -> usize>(hook) };
+    Some(hook())
+}
+
+/// The thread that polled a future, which its wakers must stay on.
+/// Zero if not known.
+#[derive(Default)]
+pub(crate) struct Owner(AtomicUsize);
+
+impl Owner {
+    /// Makes the current thread the owner.
+    pub(crate) fn claim(&self) {
+        if let Some(id) = current_thread() { self.0.store(id, Relaxed) }
+    }
+
+    /// Forgets the owner, so no further checks are made.
+    #[cfg(feature="alloc")]
+    pub(crate) fn release(&self) { self.0.store(0, Relaxed) }
+
+    /// Panics if the current thread is known not to be the owner.
+    pub(crate) fn check(&self, what: &str) {
+        let owner = self.0.load(Relaxed);
+        if owner == 0 { return; }
+        if let Some(id) = current_thread() {
+            if id != owner {
+                panic!("{} waker used on a different thread from the one that polled it", what);
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::future::Future;
+use core::ops::{Add, AddAssign, Sub};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use pin_project_lite::pin_project;
+
+/// A virtual clock for testing code with timeouts deterministically.
+///
+/// Time only moves when you call [`Clock::advance`] or
+/// [`Clock::advance_to`]. Timers that expire are woken with the waker
+/// they were last polled with, so timer firings show up in the
+/// [`crate::Stats`] of whichever executor is polling them.
+///
+/// ## Examples
+///
+/// ```
+/// use core::task::Poll;
+/// use core::time::Duration;
+/// use wookie::{Clock, wookie};
+/// let clock = Clock::new();
+/// wookie!(future: clock.sleep(Duration::from_secs(5)));
+/// assert_eq!(future.poll(), Poll::Pending);
+///
+/// clock.advance(Duration::from_secs(4));
+/// assert_eq!(future.woken(), 0);
+/// clock.advance(Duration::from_secs(1));
+/// assert_eq!(future.woken(), 1);
+/// assert_eq!(future.poll(), Poll::Ready(()));
+/// future.stats().assert(1, 1, 1);
+/// ```
+///
+/// It works just as well with [`crate::Local`]:
+///
+/// ```
+/// use core::task::Poll;
+/// use core::time::Duration;
+/// use wookie::{Clock, local};
+/// let clock = Clock::new();
+/// local!(future: clock.timeout(Duration::from_secs(1), core::future::pending::<()>()));
+/// assert_eq!(unsafe { future.poll() }, Poll::Pending);
+/// clock.advance(Duration::from_secs(1));
+/// assert!(matches!(unsafe { future.poll() }, Poll::Ready(Err(_))));
+/// future.stats().assert(1, 1, 1);
+/// ```
+#[derive(Default)]
+pub struct Clock {
+    now:     Cell<Instant>,
+    next_id: Cell<u64>,
+    timers:  RefCell<Vec<Timer>>,
+}
+
+struct Timer {
+    id:       u64,
+    deadline: Instant,
+    waker:    Waker,
+}
+
+/// A point in virtual time, measured from the creation of its [`Clock`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Instant(Duration);
+
+impl Instant {
+    /// The time since the [`Clock`] was created.
+    #[inline(always)]
+    pub fn since_start(self) -> Duration { self.0 }
+
+    /// The time elapsed from `earlier` to `self`, or zero if `earlier`
+    /// is later.
+    #[inline(always)]
+    pub fn saturating_duration_since(self, earlier: Instant) -> Duration {
+        self.0.checked_sub(earlier.0).unwrap_or_default()
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+    fn add(self, rhs: Duration) -> Instant { Instant(self.0 + rhs) }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) { self.0 += rhs }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+    fn sub(self, rhs: Instant) -> Duration { self.0 - rhs.0 }
+}
+
+impl Clock {
+    /// Creates a new [`Clock`] at time zero.
+    #[inline(always)]
+    pub fn new() -> Self { Clock::default() }
+
+    /// The current virtual time.
+    #[inline(always)]
+    pub fn now(&self) -> Instant { self.now.get() }
+
+    /// The number of timers waiting to expire.
+    #[inline(always)]
+    pub fn timers(&self) -> usize { self.timers.borrow().len() }
+
+    /// The deadline of the timer that will expire next, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers.borrow().iter().map(|t| t.deadline).min()
+    }
+
+    /// Moves time forward by `duration`, waking any timers that
+    /// expire. Returns how many timers were woken.
+    #[inline(always)]
+    pub fn advance(&self, duration: Duration) -> usize {
+        self.advance_to(self.now() + duration)
+    }
+
+    /// Moves time forward to `deadline`, waking any timers that
+    /// expire in deadline order. Returns how many timers were woken.
+    /// Does not move time backwards.
+    pub fn advance_to(&self, deadline: Instant) -> usize {
+        if deadline > self.now() { self.now.set(deadline); }
+        let now = self.now();
+        let mut expired = Vec::new();
+        {
+            let mut timers = self.timers.borrow_mut();
+            let mut i = 0;
+            while i < timers.len() {
+                if timers[i].deadline <= now {
+                    expired.push(timers.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        expired.sort_by_key(|t| (t.deadline, t.id));
+        let count = expired.len();
+        for timer in expired { timer.waker.wake() }
+        count
+    }
+
+    /// A future that completes once `duration` has passed.
+    #[inline(always)]
+    pub fn sleep(&self, duration: Duration) -> Sleep<'_> {
+        self.sleep_until(self.now() + duration)
+    }
+
+    /// A future that completes once the time is `deadline`.
+    #[inline(always)]
+    pub fn sleep_until(&self, deadline: Instant) -> Sleep<'_> {
+        Sleep { clock: self, deadline, id: None }
+    }
+
+    /// Runs `future`, failing with [`Elapsed`] if it has not completed
+    /// once `duration` has passed.
+    #[inline(always)]
+    pub fn timeout<F: Future>(&self, duration: Duration, future: F) -> Timeout<'_, F> {
+        Timeout { future, sleep: self.sleep(duration) }
+    }
+
+    fn register(&self, id: &mut Option<u64>, deadline: Instant, waker: &Waker) {
+        let mut timers = self.timers.borrow_mut();
+        if let Some(id) = *id {
+            if let Some(timer) = timers.iter_mut().find(|t| t.id == id) {
+                if !timer.waker.will_wake(waker) { timer.waker = waker.clone(); }
+                return;
+            }
+        }
+        let new = self.next_id.get();
+        self.next_id.set(new + 1);
+        *id = Some(new);
+        timers.push(Timer { id: new, deadline, waker: waker.clone() });
+    }
+
+    fn deregister(&self, id: u64) {
+        let timer = {
+            let mut timers = self.timers.borrow_mut();
+            timers.iter().position(|t| t.id == id).map(|i| timers.swap_remove(i))
+        };
+        drop(timer);
+    }
+}
+
+/// A future that completes at a deadline on a [`Clock`].
+///
+/// Created by [`Clock::sleep`] and [`Clock::sleep_until`].
+pub struct Sleep<'c> {
+    clock:    &'c Clock,
+    deadline: Instant,
+    id:       Option<u64>,
+}
+
+impl<'c> Sleep<'c> {
+    /// The time at which this future completes.
+    #[inline(always)]
+    pub fn deadline(&self) -> Instant { self.deadline }
+}
+
+impl<'c> Future for Sleep<'c> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        if this.clock.now() >= this.deadline {
+            if let Some(id) = this.id.take() { this.clock.deregister(id) }
+            Poll::Ready(())
+        } else {
+            this.clock.register(&mut this.id, this.deadline, ctx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+impl<'c> Drop for Sleep<'c> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() { self.clock.deregister(id) }
+    }
+}
+
+pin_project! {
+    /// A future that fails with [`Elapsed`] if its inner future does
+    /// not complete by a deadline on a [`Clock`].
+    ///
+    /// Created by [`Clock::timeout`].
+    pub struct Timeout<'c, F> {
+        #[pin]
+        future: F,
+        sleep:  Sleep<'c>,
+    }
+}
+
+impl<'c, F: Future> Future for Timeout<'c, F> {
+    type Output = Result<F::Output, Elapsed>;
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(r) = this.future.poll(ctx) { return Poll::Ready(Ok(r)); }
+        Pin::new(this.sleep).poll(ctx).map(|()| Err(Elapsed))
+    }
+}
+
+/// The error returned by a [`Timeout`] whose deadline passed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("deadline has elapsed") }
+}
@@ -1,10 +1,39 @@
-use super::Stats;
+use crate::{Check, Clock, Count, WakePolicy, Event, EventKind, LostWakeup, Stats, WakerId, assert_events};
+use crate::lock::Lock;
+use crate::thread::Owner;
+use alloc::boxed::Box;
+use alloc::format;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
 use core::future::Future;
 use core::mem::ManuallyDrop;
 use core::pin::Pin;
 use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
-use core::sync::atomic::{AtomicU16, Ordering::Relaxed};
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+#[cfg(not(any(feature="counter-u32", feature="counter-u64")))]
+use core::sync::atomic::AtomicU16 as AtomicCount;
+#[cfg(all(feature="counter-u32", not(feature="counter-u64")))]
+use core::sync::atomic::AtomicU32 as AtomicCount;
+#[cfg(feature="counter-u64")]
+use core::sync::atomic::AtomicU64 as AtomicCount;
+use core::time::Duration;
+#[cfg(feature="futures-core")]
+use futures_core::Stream;
+#[cfg(feature="futures-sink")]
+use crate::{SinkPhase, sink::SinkStats};
+#[cfg(feature="futures-sink")]
+use futures_sink::Sink;
+#[cfg(feature="futures-io")]
+use crate::FuturesIo;
+#[cfg(feature="tokio")]
+use crate::TokioIo;
+#[cfg(feature="std")]
+use std::backtrace::Backtrace;
+#[cfg(feature="std")]
+use crate::PanicPayload;
+#[cfg(feature="std")]
+use crate::check::catch_poll;
 
 /// A single-future stepping executor for test suites that tracks wakers.
 ///
@@ -28,14 +57,55 @@ use core::sync::atomic::{AtomicU16, Ordering::Relaxed};
 /// // or equivalently...
 /// future.stats().assert(0, 0, 0);
 /// ```
+///
+/// ## Waker identity
+///
+/// By default, every waker we hand out is the same as far as
+/// `Waker::will_wake` can tell, so futures that only re-register when
+/// the waker changes behave as they would on a real executor:
+///
+/// ```
+/// use core::future::poll_fn;
+/// use core::task::{Poll, Waker};
+/// use wookie::wookie;
+/// let mut stored: Option<Waker> = None;
+/// wookie!(future: poll_fn(|ctx| {
+///     match &stored {
+///         Some(waker) if waker.will_wake(ctx.waker()) => {}
+///         _ => stored = Some(ctx.waker().clone()),
+///     }
+///     Poll::<()>::Pending
+/// }));
+/// for _ in 0..3 { assert_eq!(future.poll(), Poll::Pending); }
+/// future.stats().assert(1, 0, 0); // cloned once, then reused
+/// ```
+///
+/// [`Wookie::enable_log`], [`Wookie::check_leaked_wakers`] and
+/// [`Wookie::capture_backtraces`] tell wakers apart by giving each
+/// clone a [`WakerId`]. That gives each its own address, so from then
+/// on `will_wake` is false for any two of them and such futures will
+/// clone on every poll.
 pub struct Wookie<F> {
     wakey: Arc<Wakey>,
     ptr: *const Wakey,
-    future: F,
+    lost_wakeups: Check,
+    lost_wakeup: Option<LostWakeup>,
+    check_leaks: bool,
+    // Whether `enable_log` was called, so the log stays on when
+    // backtraces are turned off again.
+    #[cfg(feature="std")]
+    logging: bool,
+    #[cfg(feature="futures-sink")]
+    sink: SinkStats,
+    #[cfg(feature="std")]
+    poisoned: bool,
+    terminated: bool,
+    repoll: bool,
+    future: ManuallyDrop<F>,
 }
 
 
-impl<F: Future> Wookie<F> {
+impl<F> Wookie<F> {
 
     /// Creates a new [`Wookie`] without pinning it to the stack. You
     /// probably want the [`crate::wookie!`] macro.
@@ -43,20 +113,34 @@ impl<F: Future> Wookie<F> {
     pub fn new(future: F) -> Wookie<F> {
         let ptr = Arc::into_raw(Arc::new(Wakey::default()));
         let wakey = unsafe { Arc::from_raw(ptr) };
-        Wookie { wakey, ptr, future }
+        Wookie {
+            wakey, ptr,
+            lost_wakeups: Check::Off,
+            lost_wakeup: None,
+            check_leaks: false,
+            #[cfg(feature="std")]
+            logging: false,
+            #[cfg(feature="futures-sink")]
+            sink: SinkStats::default(),
+            #[cfg(feature="std")]
+            poisoned: false,
+            terminated: false,
+            repoll: false,
+            future: ManuallyDrop::new(future),
+        }
     }
 
     /// Returns how many times the waker has been woken. This count is
     /// cumulative, it is never reset and is allowed to overflow.
     #[inline(always)]
-    pub fn woken(self: &mut Pin<&mut Self>) -> u16 {
+    pub fn woken(self: &mut Pin<&mut Self>) -> Count {
         self.as_mut().project().wakey.woken.load(Relaxed)
     }
 
     /// Returns how many times the waker has been cloned. This count is
     /// cumulative, it is never reset and is allowed to overflow.
     #[inline(always)]
-    pub fn cloned(self: &mut Pin<&mut Self>) -> u16 {
+    pub fn cloned(self: &mut Pin<&mut Self>) -> Count {
         self.as_mut().project().wakey.cloned.load(Relaxed)
     }
 
@@ -64,29 +148,392 @@ impl<F: Future> Wookie<F> {
     /// dropped. This count is cumulative, it is never reset and is
     /// allowed to overflow.
     #[inline(always)]
-    pub fn dropped(self: &mut Pin<&mut Self>) -> u16 {
+    pub fn dropped(self: &mut Pin<&mut Self>) -> Count {
         self.as_mut().project().wakey.dropped.load(Relaxed)
     }
 
     /// Returns statistics about use of our wakers.
     #[inline(always)]
     pub fn stats(self: &mut Pin<&mut Self>) -> Stats {
-        let wakey = self.as_mut().project().wakey.as_ref();
-        Stats {
-            cloned:  wakey.cloned.load(Relaxed),
-            dropped: wakey.dropped.load(Relaxed),
-            woken:   wakey.woken.load(Relaxed),
-        }
+        self.as_mut().project().wakey.stats()
     }
     /// Returns how many times a clone of the waker has been
     /// dropped. This count is cumulative, it is never reset and is
     /// allowed to overflow.
     #[inline(always)]
-    pub fn live(self: &mut Pin<&mut Self>) -> u16 {
-        let wakey = self.as_mut().project().wakey.as_ref();
-        wakey.cloned.load(Relaxed) - wakey.dropped.load(Relaxed)
+    pub fn live(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.stats().live()
+    }
+
+    /// Returns how many times the future has been polled. This count
+    /// is cumulative, it is never reset and is allowed to overflow.
+    #[inline(always)]
+    pub fn polls(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.polls.load(Relaxed)
+    }
+
+    /// Returns how many polls of the future returned `Pending`.
+    #[inline(always)]
+    pub fn pending(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.pending.load(Relaxed)
+    }
+
+    /// Returns how many polls were not preceded by a wake since the
+    /// previous poll began.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use wookie::wookie;
+    /// wookie!(future: core::future::pending::<()>());
+    /// future.poll();
+    /// future.poll(); // nobody woke it.
+    /// assert_eq!(future.spurious(), 1);
+    /// ```
+    #[inline(always)]
+    pub fn spurious(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.spurious.load(Relaxed)
+    }
+
+    /// Checks for lost wakeups after each poll: a poll that returns
+    /// `Pending` with no live wakers that did not wake the future
+    /// either, so nothing can ever wake it again.
+    ///
+    /// With [`Check::Record`], the most recent one can be retrieved
+    /// with [`Wookie::take_lost_wakeup`].
+    ///
+    /// ## Example
+    ///
+    /// ```should_panic
+    /// use wookie::{Check, wookie};
+    /// wookie!(future: core::future::pending::<()>());
+    /// future.check_lost_wakeups(Check::Panic);
+    /// future.poll(); // panics: nobody kept the waker.
+    /// ```
+    #[inline(always)]
+    pub fn check_lost_wakeups(self: &mut Pin<&mut Self>, mode: Check) {
+        self.as_mut().project().lost_wakeups = mode;
+    }
+
+    /// Returns the most recent lost wakeup recorded by
+    /// [`Wookie::check_lost_wakeups`], if any, and forgets it.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use wookie::{Check, wookie};
+    /// wookie!(future: core::future::pending::<()>());
+    /// future.check_lost_wakeups(Check::Record);
+    /// future.poll();
+    /// let lost = future.take_lost_wakeup().unwrap();
+    /// assert_eq!(lost.poll, 1);
+    /// lost.stats.assert(0, 0, 0);
+    /// assert!(future.take_lost_wakeup().is_none());
+    /// ```
+    #[inline(always)]
+    pub fn take_lost_wakeup(self: &mut Pin<&mut Self>) -> Option<LostWakeup> {
+        self.as_mut().project().lost_wakeup.take()
+    }
+
+    /// Checks that no wakers are still alive when the [`Wookie`] is
+    /// dropped, after dropping the future. With the `std` feature, the
+    /// panic message includes a backtrace of where each leaked waker
+    /// was cloned, which is captured on every clone from now on.
+    ///
+    /// ## Panics
+    ///
+    /// On drop, if any wakers are still alive, unless the thread is
+    /// already panicking (only detectable with the `std` feature).
+    ///
+    /// ## Example
+    ///
+    /// ```should_panic
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::wookie;
+    /// let mut stash = None;
+    /// {
+    ///     wookie!(future: poll_fn(|ctx| {
+    ///         stash = Some(ctx.waker().clone());
+    ///         Poll::<()>::Pending
+    ///     }));
+    ///     future.check_leaked_wakers(true);
+    ///     future.poll();
+    /// } // panics: the waker in `stash` is still alive.
+    /// ```
+    #[inline(always)]
+    pub fn check_leaked_wakers(self: &mut Pin<&mut Self>, enabled: bool) {
+        let this = self.as_mut().project();
+        this.check_leaks = enabled;
+        #[cfg(feature="std")]
+        let enabled = enabled || this.wakey.capturing.load(Relaxed);
+        this.wakey.tracking.store(enabled, Relaxed);
+    }
+
+    /// Returns whether the future has completed or the stream has
+    /// ended.
+    #[inline(always)]
+    pub fn is_terminated(self: &mut Pin<&mut Self>) -> bool {
+        self.as_mut().project().terminated
     }
 
+    /// Allows polling again after the future has completed or the
+    /// stream has ended, for testing fused futures and streams. By
+    /// default, this panics, as many futures do not expect it.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::wookie;
+    /// wookie!(future: poll_fn(|_| Poll::Ready(())));
+    /// assert_eq!(future.poll(), Poll::Ready(()));
+    /// assert!(future.is_terminated());
+    /// future.allow_repoll(true);
+    /// assert_eq!(future.poll(), Poll::Ready(()));
+    /// ```
+    #[inline(always)]
+    pub fn allow_repoll(self: &mut Pin<&mut Self>, allowed: bool) {
+        self.as_mut().project().repoll = allowed;
+    }
+
+    /// Returns whether the future panicked in [`Wookie::try_poll`].
+    /// A poisoned wookie panics if polled again.
+    #[cfg(feature="std")]
+    #[inline(always)]
+    pub fn is_poisoned(self: &mut Pin<&mut Self>) -> bool {
+        self.as_mut().project().poisoned
+    }
+
+    /// Checks that our wakers are only used on the thread that last
+    /// polled the future, panicking if not. Needs the `std` feature or
+    /// [`crate::set_thread_id_hook`].
+    ///
+    /// Our wakers are thread safe, so this is purely an assertion, for
+    /// futures that are supposed to stay on one thread.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::wookie;
+    /// // only needed without the `std` feature.
+    /// std::thread_local!(static ID: u8 = 0);
+    /// wookie::set_thread_id_hook(|| ID.with(|id| id as *const u8 as usize));
+    ///
+    /// wookie!(future: poll_fn(|ctx| {
+    ///     let waker = ctx.waker().clone();
+    ///     // panics: the waker was woken on another thread.
+    ///     let panic = std::thread::spawn(move || waker.wake()).join().unwrap_err();
+    ///     assert_eq!(
+    ///         panic.downcast_ref::<String>().map(String::as_str),
+    ///         Some("Wookie waker used on a different thread from the one that polled it"),
+    ///     );
+    ///     Poll::<()>::Pending
+    /// }));
+    /// future.same_thread_only(true);
+    /// assert_eq!(future.poll(), Poll::Pending);
+    /// ```
+    #[inline(always)]
+    pub fn same_thread_only(self: &mut Pin<&mut Self>, enabled: bool) {
+        let this = self.as_mut().project();
+        this.wakey.same_thread.store(enabled, Relaxed);
+        if enabled { this.wakey.owner.claim() } else { this.wakey.owner.release() }
+    }
+
+    /// Captures a backtrace on every clone, wake and drop of our wakers
+    /// from now on, storing it with the event in the event log. Also
+    /// enables the event log and keeps track of which wakers are live,
+    /// until called again with `false`, which puts both back how they
+    /// were.
+    ///
+    /// Backtraces are slow to capture, so you probably only want this
+    /// while hunting down a particular bug.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::wookie;
+    /// let mut stash = None;
+    /// wookie!(future: poll_fn(|ctx| {
+    ///     stash = Some(ctx.waker().clone());
+    ///     Poll::<()>::Pending
+    /// }));
+    /// future.capture_backtraces(true);
+    /// future.poll();
+    /// future.print_live_backtraces(); // where was the stashed waker cloned?
+    /// ```
+    ///
+    /// Once turned off again, our wakers are the same to `will_wake`
+    /// again (see [`Wookie`]):
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::{Poll, Waker};
+    /// use wookie::wookie;
+    /// let mut stored: Option<Waker> = None;
+    /// wookie!(future: poll_fn(|ctx| {
+    ///     match &stored {
+    ///         Some(waker) if waker.will_wake(ctx.waker()) => {}
+    ///         _ => stored = Some(ctx.waker().clone()),
+    ///     }
+    ///     Poll::<()>::Pending
+    /// }));
+    /// future.capture_backtraces(true);
+    /// assert_eq!(future.poll(), Poll::Pending);
+    /// future.capture_backtraces(false);
+    /// for _ in 0..3 { assert_eq!(future.poll(), Poll::Pending); }
+    /// future.stats().assert(2, 1, 0); // cloned once more, then reused
+    /// ```
+    #[cfg(feature="std")]
+    pub fn capture_backtraces(self: &mut Pin<&mut Self>, enabled: bool) {
+        let this = self.as_mut().project();
+        this.wakey.capturing.store(enabled, Relaxed);
+        if enabled {
+            this.wakey.logging.store(true, Relaxed);
+            this.wakey.tracking.store(true, Relaxed);
+        } else {
+            this.wakey.logging.store(this.logging, Relaxed);
+            this.wakey.tracking.store(this.check_leaks, Relaxed);
+        }
+    }
+
+    /// Prints where each waker that is still alive was cloned to
+    /// stderr. Only covers clones made while
+    /// [`Wookie::capture_backtraces`] or [`Wookie::check_leaked_wakers`]
+    /// was enabled.
+    #[cfg(feature="std")]
+    pub fn print_live_backtraces(self: &mut Pin<&mut Self>) {
+        self.as_mut().project().wakey.live.with(|live| {
+            std::eprintln!("wookie: {} live wakers tracked", live.len());
+            for waker in live.iter() {
+                std::eprintln!("{} was cloned at:\n{}", waker.id, waker.backtrace);
+            }
+        })
+    }
+
+    /// Prints where the most recent wake happened to stderr, if it
+    /// happened while [`Wookie::capture_backtraces`] was enabled.
+    #[cfg(feature="std")]
+    pub fn print_last_wake_backtrace(self: &mut Pin<&mut Self>) {
+        self.as_mut().project().wakey.log.with(|log| {
+            let last = log.iter().rev().find(|logged| {
+                matches!(logged.event.kind, EventKind::Wake | EventKind::WakeByRef)
+                    && logged.backtrace.is_some()
+            });
+            match last {
+                Some(logged) => std::eprintln!(
+                    "wookie: most recent wake was {} at:\n{}",
+                    logged.event, logged.backtrace.as_ref().unwrap()
+                ),
+                None => std::eprintln!("wookie: no wakes with backtraces recorded"),
+            }
+        })
+    }
+
+    /// Prints every event in the event log to stderr, along with its
+    /// backtrace if one was captured.
+    #[cfg(feature="std")]
+    pub fn print_event_backtraces(self: &mut Pin<&mut Self>) {
+        self.as_mut().project().wakey.log.with(|log| {
+            for logged in log.iter() {
+                match &logged.backtrace {
+                    Some(backtrace) => std::eprintln!("{} at:\n{}", logged.event, backtrace),
+                    None => std::eprintln!("{}", logged.event),
+                }
+            }
+        })
+    }
+
+    /// Starts recording every clone, wake and drop of our wakers in an
+    /// event log, tagged with the poll it happened during.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::{EventKind::*, wookie};
+    /// let mut waker = None;
+    /// wookie!(future: poll_fn(|ctx| {
+    ///     waker = Some(ctx.waker().clone());
+    ///     Poll::<()>::Pending
+    /// }));
+    /// future.enable_log();
+    /// assert_eq!(future.poll(), Poll::Pending);
+    /// assert_eq!(future.poll(), Poll::Pending);
+    /// future.assert_events(&[Clone.during(1), Clone.during(2), Drop.during(2)]);
+    /// ```
+    #[inline(always)]
+    pub fn enable_log(self: &mut Pin<&mut Self>) {
+        let this = self.as_mut().project();
+        #[cfg(feature="std")]
+        { this.logging = true; }
+        this.wakey.logging.store(true, Relaxed);
+    }
+
+    /// Returns the events recorded since [`Wookie::enable_log`] was
+    /// called, oldest first.
+    pub fn events(self: &mut Pin<&mut Self>) -> Vec<Event> {
+        self.as_mut().project().wakey.log.with(|log| log.iter().map(|l| l.event).collect())
+    }
+
+    /// Forgets all events recorded so far.
+    pub fn clear_events(self: &mut Pin<&mut Self>) {
+        self.as_mut().project().wakey.log.with(|log| log.clear())
+    }
+
+    /// Asserts that the events recorded so far are exactly `expected`.
+    #[track_caller]
+    pub fn assert_events(self: &mut Pin<&mut Self>, expected: &[Event]) {
+        assert_events(self.events(), expected)
+    }
+
+    /// Returns the identity of the waker that was most recently woken.
+    /// Only wakers with an identity count, see [Waker
+    /// identity](Wookie#waker-identity).
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::cell::RefCell;
+    /// use core::future::poll_fn;
+    /// use core::task::{Poll, Waker};
+    /// use wookie::wookie;
+    /// let wakers: RefCell<Vec<Waker>> = RefCell::new(Vec::new());
+    /// wookie!(future: poll_fn(|ctx| {
+    ///     wakers.borrow_mut().push(ctx.waker().clone());
+    ///     Poll::<()>::Pending
+    /// }));
+    /// future.enable_log();
+    /// assert_eq!(future.poll(), Poll::Pending);
+    /// assert_eq!(future.poll(), Poll::Pending);
+    /// // a waker from the first poll is woken after the second.
+    /// wakers.borrow_mut().remove(0).wake();
+    /// let id = future.last_wake().unwrap();
+    /// assert_eq!((id.clone, id.poll, id.parent), (1, 1, Some(0)));
+    /// assert_eq!(future.stale_wakes(), 1);
+    /// ```
+    pub fn last_wake(self: &mut Pin<&mut Self>) -> Option<WakerId> {
+        self.as_mut().project().wakey.last_wake.with(|w| *w)
+    }
+
+    /// Returns how many times a waker that came from an earlier poll
+    /// than the most recent one has been woken. Futures that store a
+    /// waker and never update it tend to do this. Only wakers with an
+    /// identity count, see [Waker identity](Wookie#waker-identity).
+    #[inline(always)]
+    pub fn stale_wakes(self: &mut Pin<&mut Self>) -> Count {
+        self.as_mut().project().wakey.stale.load(Relaxed)
+    }
+
+}
+
+impl<F: Future> Wookie<F> {
     /// Polls the contained future once.
     ///
     /// ## Example
@@ -101,11 +548,62 @@ impl<F: Future> Wookie<F> {
     pub fn poll(
         self: &mut Pin<&mut Self>
     ) -> Poll<<F as Future>::Output> {
-        let this = self.as_mut().project();
-        let waker = ManuallyDrop::new(this.waker());
-        let future = unsafe { Pin::new_unchecked(&mut this.future) };
-        let mut ctx = Context::from_waker(&waker);
-        Future::poll(future, &mut ctx)
+        let ret = self.step(Future::poll);
+        self.terminate_if(ret.is_ready());
+        ret
+    }
+
+    /// Polls the contained future once, catching a panic instead of
+    /// letting it unwind through the test.
+    ///
+    /// If the future panics, the wookie is poisoned. Its stats can
+    /// still be read, but polling it again panics.
+    ///
+    /// ## Panics
+    ///
+    /// Like [`Wookie::poll`], if the wookie is poisoned or the future
+    /// has completed. These are not caught:
+    ///
+    /// ```should_panic
+    /// use core::task::Poll;
+    /// use wookie::wookie;
+    /// wookie!(future: async { true });
+    /// assert_eq!(future.poll(), Poll::Ready(true));
+    /// let _ = future.try_poll(); // panics rather than returning `Err`
+    /// ```
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::future::poll_fn;
+    /// use core::task::Poll;
+    /// use wookie::wookie;
+    /// let mut stash = None;
+    /// wookie!(future: poll_fn(|ctx| -> Poll<()> {
+    ///     stash = Some(ctx.waker().clone());
+    ///     panic!("oops")
+    /// }));
+    /// let payload = future.try_poll().unwrap_err();
+    /// assert_eq!(payload.downcast_ref::<&str>(), Some(&"oops"));
+    /// assert!(future.is_poisoned());
+    /// // It kept a waker before it panicked.
+    /// future.stats().assert(1, 0, 0);
+    /// ```
+    #[cfg(feature="std")]
+    pub fn try_poll(
+        self: &mut Pin<&mut Self>
+    ) -> Result<Poll<<F as Future>::Output>, PanicPayload> {
+        match self.step(catch_poll) {
+            Poll::Ready(Ok(out)) => {
+                self.terminate_if(true);
+                Ok(Poll::Ready(out))
+            }
+            Poll::Ready(Err(payload)) => {
+                self.as_mut().project().poisoned = true;
+                Err(payload)
+            }
+            Poll::Pending => Ok(Poll::Pending),
+        }
     }
 
     /// Polls the contained future until completion, so long as the
@@ -123,31 +621,332 @@ impl<F: Future> Wookie<F> {
     pub fn poll_while_woken(
         self: &mut Pin<&mut Self>
     ) -> Poll<<F as Future>::Output> {
-        let mut woken = self.woken();
+        self.poll_while_woken_by(WakePolicy::Any)
+    }
+
+    /// Like [`Wookie::poll_while_woken`], but only polls again if the
+    /// previous poll caused wakes that count under `policy`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use wookie::{WakePolicy, wookie};
+    /// wookie!(future: async { true });
+    /// assert_eq!(future.poll_while_woken_by(WakePolicy::SelfWake), Poll::Ready(true));
+    /// ```
+    pub fn poll_while_woken_by(
+        self: &mut Pin<&mut Self>,
+        policy: WakePolicy,
+    ) -> Poll<<F as Future>::Output> {
+        let ret = self.step_while_woken(policy, Future::poll);
+        self.terminate_if(ret.is_ready());
+        ret
+    }
+
+    /// Like [`Wookie::poll_while_woken`], but whenever the future stops
+    /// being woken, moves the [`Clock`] forward to the next timer
+    /// deadline, so timeouts fire instantly. Stops when the future
+    /// completes or there are no timers left. Returns the virtual time
+    /// that passed along with the result.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use core::time::Duration;
+    /// use wookie::{Clock, wookie};
+    /// let clock = Clock::new();
+    /// wookie!(future: async {
+    ///     clock.sleep(Duration::from_secs(60)).await;
+    ///     clock.sleep(Duration::from_secs(60)).await;
+    ///     true
+    /// });
+    /// let (poll, elapsed) = future.run_until_stalled_with_time(&clock);
+    /// assert_eq!(poll, Poll::Ready(true));
+    /// assert_eq!(elapsed, Duration::from_secs(120));
+    /// assert_eq!(future.woken(), 2);
+    /// ```
+    pub fn run_until_stalled_with_time(
+        self: &mut Pin<&mut Self>,
+        clock: &Clock,
+    ) -> (Poll<<F as Future>::Output>, Duration) {
+        let start = clock.now();
         loop {
-            if let Poll::Ready(r) = self.poll() { return Poll::Ready(r); }
-            let w = self.woken();
+            if let Poll::Ready(r) = self.poll_while_woken() {
+                return (Poll::Ready(r), clock.now() - start);
+            }
+            let woken = self.woken();
+            while self.woken() == woken {
+                match clock.next_deadline() {
+                    Some(deadline) => { clock.advance_to(deadline); }
+                    None => return (Poll::Pending, clock.now() - start),
+                }
+            }
+        }
+    }
+
+}
+
+impl<F> Wookie<F> {
+    /// Runs `f` with [`Wookie::step`] until it is ready, so long as the
+    /// previous run caused wakes that count under `policy`.
+    fn step_while_woken<R>(
+        self: &mut Pin<&mut Self>,
+        policy: WakePolicy,
+        mut f: impl FnMut(Pin<&mut F>, &mut Context) -> Poll<R>,
+    ) -> Poll<R> {
+        let mut woken = policy.count(&self.stats());
+        loop {
+            if let Poll::Ready(r) = self.step(&mut f) { return Poll::Ready(r); }
+            let w = policy.count(&self.stats());
             if w == woken { return Poll::Pending; }
             woken = w;
         }
     }
 
+    /// Remembers that the future has completed or the stream has
+    /// ended if `done`.
     #[inline(always)]
-    fn waker(&self) -> Waker {
-        // Safety: the returned waker is valid as long as self is
-        // valid. But in order to do anything mutable with the Waker,
-        // they would have to have cloned it first.
-        let raw = wookie_rawwaker(self.ptr);
-        unsafe { Waker::from_raw(raw) }
+    fn terminate_if(self: &mut Pin<&mut Self>, done: bool) {
+        if done { self.as_mut().project().terminated = true; }
+    }
+
+    /// Runs one poll of the future with our waker.
+    #[inline(always)]
+    pub(crate) fn step<R>(
+        self: &mut Pin<&mut Self>,
+        f: impl FnOnce(Pin<&mut F>, &mut Context) -> Poll<R>,
+    ) -> Poll<R> {
+        let this = self.as_mut().project();
+        #[cfg(feature="std")]
+        assert!(!this.poisoned, "Wookie polled again after its future panicked");
+        assert!(
+            !this.terminated || this.repoll,
+            "Wookie polled again after it completed. Use allow_repoll to allow this."
+        );
+        let woken = this.wakey.woken.load(Relaxed);
+        let poll = this.wakey.begin_poll();
+        let node = Node { wakey: this.ptr, id: WakerId { clone: 0, poll, parent: None } };
+        let waker = ManuallyDrop::new(if this.wakey.identifying() {
+            node_waker(&node)
+        } else {
+            plain_waker(this.ptr)
+        });
+        let future = unsafe { Pin::new_unchecked(&mut *this.future) };
+        let mut ctx = Context::from_waker(&waker);
+        let ret = f(future, &mut ctx);
+        this.wakey.end_poll(ret.is_pending());
+        if this.lost_wakeups != Check::Off {
+            let lost = LostWakeup::check(poll, ret.is_pending(), woken, this.wakey.stats());
+            LostWakeup::handle(this.lost_wakeups, lost, &mut this.lost_wakeup);
+        }
+        ret
+    }
+
+    /// Drops the wookie and its future, returning the stats of our
+    /// wakers afterwards.
+    pub(crate) fn drop_with_stats(this: Pin<Box<Self>>) -> Stats {
+        let wakey = this.wakey.clone();
+        drop(this);
+        wakey.stats()
+    }
+
+    /// Returns the future pinned, without polling it.
+    #[cfg(any(feature="futures-io", feature="tokio"))]
+    #[inline(always)]
+    pub(crate) fn pinned<'a>(self: &'a mut Pin<&mut Self>) -> Pin<&'a mut F> {
+        let this = self.as_mut().project();
+        unsafe { Pin::new_unchecked(&mut *this.future) }
     }
 
     #[inline(always)]
     fn project(self: Pin<&mut Self>) -> &mut Self {
         unsafe { Pin::into_inner_unchecked(self) }
     }
+}
+
+#[cfg(feature="futures-io")]
+impl<F> Wookie<F> {
+    /// Returns an adapter for stepping the contained `futures-io`
+    /// reader or writer. See [`FuturesIo`].
+    #[inline(always)]
+    pub fn futures_io<'a>(self: &'a mut Pin<&mut Self>) -> FuturesIo<'a, F> {
+        FuturesIo::new(self.as_mut())
+    }
+}
+
+#[cfg(feature="tokio")]
+impl<F> Wookie<F> {
+    /// Returns an adapter for stepping the contained `tokio` reader or
+    /// writer. See [`TokioIo`].
+    #[inline(always)]
+    pub fn tokio_io<'a>(self: &'a mut Pin<&mut Self>) -> TokioIo<'a, F> {
+        TokioIo::new(self.as_mut())
+    }
+}
+
+#[cfg(feature="futures-core")]
+impl<F: Stream> Wookie<F> {
+    /// Polls the contained stream for its next item once.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use wookie::{iter_stream, wookie};
+    /// wookie!(stream: iter_stream(1..3));
+    /// assert_eq!(stream.poll_next(), Poll::Ready(Some(1)));
+    /// assert_eq!(stream.poll_next(), Poll::Ready(Some(2)));
+    /// assert_eq!(stream.poll_next(), Poll::Ready(None));
+    /// stream.stats().assert(0, 0, 0);
+    /// ```
+    #[inline(always)]
+    pub fn poll_next(self: &mut Pin<&mut Self>) -> Poll<Option<F::Item>> {
+        let ret = self.step(Stream::poll_next);
+        self.terminate_if(matches!(ret, Poll::Ready(None)));
+        ret
+    }
+
+    /// Polls the contained stream for its next item until it gets one
+    /// or the stream ends, so long as the previous poll caused one or
+    /// more wakes.
+    #[inline(always)]
+    pub fn poll_next_while_woken(self: &mut Pin<&mut Self>) -> Poll<Option<F::Item>> {
+        let ret = self.step_while_woken(WakePolicy::Any, Stream::poll_next);
+        self.terminate_if(matches!(ret, Poll::Ready(None)));
+        ret
+    }
+
+    /// Collects items from the contained stream for as long as they
+    /// are available, as with [`Wookie::poll_next_while_woken`].
+    /// Also returns whether the stream has ended.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use wookie::{iter_stream, wookie};
+    /// wookie!(stream: iter_stream(1..4));
+    /// assert_eq!(stream.collect_while_woken(), (vec![1, 2, 3], Poll::Ready(())));
+    /// assert_eq!(stream.polls(), 4);
+    /// ```
+    pub fn collect_while_woken(self: &mut Pin<&mut Self>) -> (Vec<F::Item>, Poll<()>) {
+        let mut items = Vec::new();
+        loop {
+            match self.poll_next_while_woken() {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => return (items, Poll::Ready(())),
+                Poll::Pending => return (items, Poll::Pending),
+            }
+        }
+    }
+}
+
+#[cfg(feature="futures-sink")]
+impl<F> Wookie<F> {
+    /// Polls the contained sink's `poll_ready` once.
+    ///
+    /// ## Example
+    ///
+    /// A sink with room for one item registers a waker when it is full
+    /// and is woken once when the item is taken:
+    ///
+    /// ```
+    /// use core::task::Poll;
+    /// use wookie::{SinkPhase, assert_pending, assert_ready, assert_stats, wookie};
+    /// # use core::cell::RefCell;
+    /// # use core::pin::Pin;
+    /// # use core::task::{Context, Waker};
+    /// # use std::rc::Rc;
+    /// # #[derive(Default)]
+    /// # struct Slot { item: Option<u8>, waker: Option<Waker> }
+    /// #     /// A sink with room for one item.
+    /// # struct Tx(Rc<RefCell<Slot>>);
+    /// # impl futures_sink::Sink<u8> for Tx {
+    /// #     type Error = ();
+    /// #     fn poll_ready(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), ()>> {
+    /// #         let mut slot = self.0.borrow_mut();
+    /// #         if slot.item.is_none() { return Poll::Ready(Ok(())); }
+    /// #         slot.waker = Some(ctx.waker().clone());
+    /// #         Poll::Pending
+    /// #     }
+    /// #     fn start_send(self: Pin<&mut Self>, item: u8) -> Result<(), ()> {
+    /// #         self.0.borrow_mut().item = Some(item);
+    /// #         Ok(())
+    /// #     }
+    /// #     fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), ()>> { Poll::Ready(Ok(())) }
+    /// #     fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), ()>> { Poll::Ready(Ok(())) }
+    /// # }
+    /// #     /// Takes the item, making room.
+    /// # fn take(slot: &RefCell<Slot>) -> Option<u8> {
+    /// #     let mut slot = slot.borrow_mut();
+    /// #     if let Some(waker) = slot.waker.take() { waker.wake() }
+    /// #     slot.item.take()
+    /// # }
+    /// let slot = Rc::new(RefCell::new(Slot::default()));
+    /// wookie!(sink: Tx(slot.clone()));
+    /// assert_ready!(Ok(()), sink.poll_ready());
+    /// sink.start_send(1).unwrap();
+    /// assert_pending!(sink.poll_ready()); // full
+    /// assert_eq!(take(&slot), Some(1));
+    /// assert_stats!(sink.sink_stats(SinkPhase::Ready), polls: 2, pending: 1, cloned: 1, woken: 1);
+    /// assert_ready!(Ok(()), sink.poll_ready());
+    /// ```
+    #[inline(always)]
+    pub fn poll_ready<I>(self: &mut Pin<&mut Self>) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Ready);
+        self.step(Sink::poll_ready)
+    }
+
+    /// Calls the contained sink's `start_send`.
+    #[inline(always)]
+    pub fn start_send<I>(self: &mut Pin<&mut Self>, item: I) -> Result<(), <F as Sink<I>>::Error>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Send);
+        let this = self.as_mut().project();
+        Sink::start_send(unsafe { Pin::new_unchecked(&mut *this.future) }, item)
+    }
+
+    /// Polls the contained sink's `poll_flush` once.
+    #[inline(always)]
+    pub fn poll_flush<I>(self: &mut Pin<&mut Self>) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Flush);
+        self.step(Sink::poll_flush)
+    }
+
+    /// Polls the contained sink's `poll_close` once.
+    #[inline(always)]
+    pub fn poll_close<I>(self: &mut Pin<&mut Self>) -> Poll<Result<(), <F as Sink<I>>::Error>>
+    where F: Sink<I> {
+        self.enter_sink(SinkPhase::Close);
+        self.step(Sink::poll_close)
+    }
 
+    /// Returns the waker and poll activity put down to one of the
+    /// sink methods. See [`SinkPhase`].
+    #[inline(always)]
+    pub fn sink_stats(self: &mut Pin<&mut Self>, phase: SinkPhase) -> Stats {
+        let now = self.stats();
+        self.as_mut().project().sink.get(phase, now)
+    }
+
+    #[inline(always)]
+    fn enter_sink(self: &mut Pin<&mut Self>, phase: SinkPhase) {
+        let now = self.stats();
+        self.as_mut().project().sink.enter(phase, now)
+    }
 }
 
+impl<F> Drop for Wookie<F> {
+    fn drop(&mut self) {
+        // Safety: we never touch it again.
+        unsafe { ManuallyDrop::drop(&mut self.future) };
+        if self.check_leaks { self.wakey.check_leaks() }
+    }
+}
 
 /// Wraps a future in a single-future stepping executor for test
 /// suites that tracks wakers and pins it on the stack.
@@ -188,45 +987,246 @@ macro_rules! wookie {
 
 #[derive(Default)]
 struct Wakey {
-    cloned:  AtomicU16,
-    dropped: AtomicU16,
-    woken:   AtomicU16,
+    cloned:       AtomicCount,
+    dropped:      AtomicCount,
+    woken:        AtomicCount,
+    woken_by_ref: AtomicCount,
+    self_woken:   AtomicCount,
+    polls:        AtomicCount,
+    pending:      AtomicCount,
+    spurious:     AtomicCount,
+    /// `woken` as of the start of the last poll.
+    seen:         AtomicCount,
+    polling:      AtomicBool,
+    clones:       AtomicCount,
+    stale:        AtomicCount,
+    last_wake:    Lock<Option<WakerId>>,
+    logging:      AtomicBool,
+    log:          Lock<Vec<Logged>>,
+    tracking:     AtomicBool,
+    live:         Lock<Vec<LiveWaker>>,
+    #[cfg(feature="std")]
+    capturing:    AtomicBool,
+    same_thread:  AtomicBool,
+    owner:        Owner,
+}
+
+/// An entry in the event log.
+struct Logged {
+    event: Event,
+    #[cfg(feature="std")]
+    backtrace: Option<Backtrace>,
+}
+
+/// A clone that has not yet been dropped, tracked for leak checking.
+struct LiveWaker {
+    id: WakerId,
+    #[cfg(feature="std")]
+    backtrace: Backtrace,
 }
 
 impl Wakey {
-    fn bump_cloned(&self)  -> u16 { self.cloned.fetch_add(1, Relaxed) }
-    fn bump_woken(&self)   -> u16 { self.woken.fetch_add(1, Relaxed) }
-    fn bump_dropped(&self) -> u16 { self.dropped.fetch_add(1, Relaxed) }
+    fn stats(&self) -> Stats {
+        Stats {
+            cloned:       self.cloned.load(Relaxed),
+            dropped:      self.dropped.load(Relaxed),
+            woken:        self.woken.load(Relaxed),
+            woken_by_ref: self.woken_by_ref.load(Relaxed),
+            self_woken:   self.self_woken.load(Relaxed),
+            polls:        self.polls.load(Relaxed),
+            pending:      self.pending.load(Relaxed),
+            spurious:     self.spurious.load(Relaxed),
+        }
+    }
+
+    fn track_clone(&self, id: WakerId) {
+        if self.tracking.load(Relaxed) {
+            #[cfg(feature="std")]
+            let live = LiveWaker { id, backtrace: Backtrace::force_capture() };
+            #[cfg(not(feature="std"))]
+            let live = LiveWaker { id };
+            self.live.with(|l| l.push(live));
+        }
+    }
+
+    fn track_drop(&self, id: WakerId) {
+        if self.tracking.load(Relaxed) {
+            self.live.with(|l| l.retain(|live| live.id != id));
+        }
+    }
+
+    fn check_leaks(&self) {
+        let stats = self.stats();
+        if stats.live() == 0 { return; }
+        #[cfg(feature="std")]
+        if std::thread::panicking() { return; }
+        let mut msg = format!(
+            "Wookie dropped with {} leaked wakers ({})",
+            stats.live(), stats
+        );
+        self.live.with(|live| {
+            for waker in live.iter() {
+                let _ = write!(msg, "\n  leaked {}", waker.id);
+                #[cfg(feature="std")]
+                let _ = write!(msg, ", cloned at:\n{}", waker.backtrace);
+            }
+        });
+        panic!("{}", msg);
+    }
+
+    fn bump_cloned(&self)  -> Count { self.cloned.fetch_add(1, Relaxed) }
+    fn bump_dropped(&self) -> Count { self.dropped.fetch_add(1, Relaxed) }
+
+    fn bump_woken(&self, id: Option<WakerId>, by_ref: bool) -> Count {
+        if let Some(id) = id {
+            if id.poll != self.polls.load(Relaxed) { self.stale.fetch_add(1, Relaxed); }
+            self.last_wake.with(|w| *w = Some(id));
+        }
+        if by_ref { self.woken_by_ref.fetch_add(1, Relaxed); }
+        if self.polling.load(Relaxed) { self.self_woken.fetch_add(1, Relaxed); }
+        self.woken.fetch_add(1, Relaxed)
+    }
+
+    fn begin_poll(&self) -> Count {
+        if self.same_thread.load(Relaxed) { self.owner.claim() }
+        let woken = self.woken.load(Relaxed);
+        let poll = self.polls.fetch_add(1, Relaxed).wrapping_add(1);
+        if self.seen.swap(woken, Relaxed) == woken && poll > 1 {
+            self.spurious.fetch_add(1, Relaxed);
+        }
+        self.polling.store(true, Relaxed);
+        poll
+    }
+
+    fn end_poll(&self, pending: bool) {
+        self.polling.store(false, Relaxed);
+        if pending { self.pending.fetch_add(1, Relaxed); }
+    }
+
+    /// Whether the wakers we hand out get their own [`WakerId`].
+    fn identifying(&self) -> bool {
+        self.logging.load(Relaxed) || self.tracking.load(Relaxed)
+    }
+
+    fn record(&self, kind: EventKind, id: Option<WakerId>) {
+        if self.logging.load(Relaxed) {
+            let poll = if self.polling.load(Relaxed) { Some(self.polls.load(Relaxed)) } else { None };
+            let event = Event { kind, poll, waker: id };
+            #[cfg(feature="std")]
+            let logged = Logged {
+                event,
+                backtrace: if self.capturing.load(Relaxed) { Some(Backtrace::force_capture()) } else { None },
+            };
+            #[cfg(not(feature="std"))]
+            let logged = Logged { event };
+            self.log.with(|log| log.push(logged));
+        }
+    }
 }
 
-fn wookie_rawwaker(wakey: *const Wakey) -> RawWaker {
+/// Safety: the returned waker is valid as long as the [`Wookie`] that
+/// owns `wakey` is. But in order to do anything mutable with the
+/// Waker, they would have to have cloned it first.
+#[inline(always)]
+fn plain_waker(wakey: *const Wakey) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(wakey as *const (), &PLAIN)) }
+}
+
+/// The vtable for wakers that point straight at the [`Wakey`] and
+/// hold a strong count on it. They all look the same to `will_wake`.
+static PLAIN: RawWakerVTable = {
     fn do_clone(data: *const ()) -> RawWaker {
-        let wakey = data as *const Wakey;
-        unsafe { &*wakey }.bump_cloned();
-        unsafe { Arc::increment_strong_count(wakey) };
-        wookie_rawwaker(wakey)
+        let wakey = unsafe { &*(data as *const Wakey) };
+        wakey.owner.check("Wookie");
+        wakey.bump_cloned();
+        wakey.record(EventKind::Clone, None);
+        unsafe { Arc::increment_strong_count(data as *const Wakey) };
+        RawWaker::new(data, &PLAIN)
     }
 
     fn do_wake(data: *const ()) {
         let wakey: Arc<Wakey> = unsafe { Arc::from_raw(data as *const Wakey) };
-        wakey.bump_woken();
+        wakey.owner.check("Wookie");
+        wakey.bump_woken(None, false);
         wakey.bump_dropped();
+        wakey.record(EventKind::Wake, None);
     }
 
     fn do_wake_by_ref(data: *const ()) {
-        let arc = unsafe { Arc::from_raw(data as *const Wakey) };
-        let wakey = ManuallyDrop::new(arc);
-        wakey.bump_woken();
+        let wakey = unsafe { &*(data as *const Wakey) };
+        wakey.owner.check("Wookie");
+        wakey.bump_woken(None, true);
+        wakey.record(EventKind::WakeByRef, None);
     }
 
     fn do_drop(data: *const ()) {
         let wakey: Arc<Wakey> = unsafe { Arc::from_raw(data as *const Wakey) };
+        wakey.owner.check("Wookie");
         wakey.bump_dropped();
+        wakey.record(EventKind::Drop, None);
     }
 
-    RawWaker::new(
-        wakey as *const (),
-        &RawWakerVTable::new(do_clone, do_wake, do_wake_by_ref, do_drop)
-    )
+    RawWakerVTable::new(do_clone, do_wake, do_wake_by_ref, do_drop)
+};
+
+/// What an identified waker's data points to. The waker passed to
+/// `poll` points to one on the stack. Each clone gets its own on the
+/// heap, which holds a strong count on the [`Wakey`].
+struct Node {
+    wakey: *const Wakey,
+    id:    WakerId,
 }
 
+/// Safety: the returned waker is valid as long as `node` is. But in
+/// order to do anything mutable with the Waker, they would have to
+/// have cloned it first.
+#[inline(always)]
+fn node_waker(node: &Node) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(node as *const Node as *const (), &NODE)) }
+}
+
+/// The vtable for wakers with their own [`WakerId`].
+static NODE: RawWakerVTable = {
+    fn do_clone(data: *const ()) -> RawWaker {
+        let node = unsafe { &*(data as *const Node) };
+        let wakey = unsafe { &*node.wakey };
+        wakey.owner.check("Wookie");
+        wakey.bump_cloned();
+        let clone = wakey.clones.fetch_add(1, Relaxed).wrapping_add(1);
+        let id = WakerId { clone, poll: node.id.poll, parent: Some(node.id.clone) };
+        wakey.record(EventKind::Clone, Some(id));
+        wakey.track_clone(id);
+        unsafe { Arc::increment_strong_count(node.wakey) };
+        let clone = Box::into_raw(Box::new(Node { wakey: node.wakey, id }));
+        RawWaker::new(clone as *const (), &NODE)
+    }
+
+    fn do_wake(data: *const ()) {
+        let node = unsafe { Box::from_raw(data as *mut Node) };
+        let wakey: Arc<Wakey> = unsafe { Arc::from_raw(node.wakey) };
+        wakey.owner.check("Wookie");
+        wakey.bump_woken(Some(node.id), false);
+        wakey.bump_dropped();
+        wakey.record(EventKind::Wake, Some(node.id));
+        wakey.track_drop(node.id);
+    }
+
+    fn do_wake_by_ref(data: *const ()) {
+        let node = unsafe { &*(data as *const Node) };
+        let wakey = unsafe { &*node.wakey };
+        wakey.owner.check("Wookie");
+        wakey.bump_woken(Some(node.id), true);
+        wakey.record(EventKind::WakeByRef, Some(node.id));
+    }
+
+    fn do_drop(data: *const ()) {
+        let node = unsafe { Box::from_raw(data as *mut Node) };
+        let wakey: Arc<Wakey> = unsafe { Arc::from_raw(node.wakey) };
+        wakey.owner.check("Wookie");
+        wakey.bump_dropped();
+        wakey.record(EventKind::Drop, Some(node.id));
+        wakey.track_drop(node.id);
+    }
+
+    RawWakerVTable::new(do_clone, do_wake, do_wake_by_ref, do_drop)
+};